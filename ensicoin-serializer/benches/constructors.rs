@@ -0,0 +1,48 @@
+//! Compares the `Deserializer` constructors on a 1 MB buffer of `u32`s. `new` and `from_slice`
+//! copy the input, while `from_bytes` only clones a shared `Bytes`. Each constructor is timed
+//! alone, then with the whole buffer decoded.
+//! Run with `cargo bench -p ensicoin_serializer --bench constructors`.
+
+use bytes::{Bytes, BytesMut};
+use ensicoin_serializer::{Deserialize, Deserializer};
+use std::hint::black_box;
+use std::time::Instant;
+
+const COUNT: usize = 1024 * 1024 / 4;
+
+fn measure<F: FnMut() -> Deserializer>(name: &str, iterations: u32, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    let construction = start.elapsed() / iterations;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let mut de = f();
+        let mut sum = 0_u64;
+        while !de.is_empty() {
+            sum += u32::deserialize(&mut de).unwrap() as u64;
+        }
+        black_box(sum);
+    }
+    let decoding = start.elapsed() / iterations;
+    println!(
+        "{}: {:?} to construct, {:?} to construct and decode",
+        name, construction, decoding
+    );
+}
+
+fn main() {
+    let data: Vec<u8> = (0..COUNT as u32).flat_map(|x| x.to_be_bytes()).collect();
+    let shared = Bytes::from(data.clone());
+    let iterations = 100;
+
+    measure("new", iterations, || {
+        Deserializer::new(BytesMut::from(&data[..]))
+    });
+    measure("from_slice", iterations, || Deserializer::from_slice(&data));
+    measure("from_bytes", iterations, || {
+        Deserializer::from_bytes(shared.clone())
+    });
+}
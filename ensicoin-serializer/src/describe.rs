@@ -0,0 +1,109 @@
+use super::deserializer::Deserialize;
+use super::deserializer::Deserializer;
+use super::deserializer::Result;
+use super::types::Sha256Result;
+use super::types::VarUint;
+use bytes::BytesMut;
+use serde_json::Value;
+
+pub use serde_json;
+
+/// Readable JSON rendering of a decoded value, to dump messages in tooling. Hashes and bytes
+/// vectors are rendered as hex strings
+pub trait Describe {
+    fn to_json_value(&self) -> Value;
+
+    /// Renders a slice of values, as an array unless overridden like for bytes
+    fn describe_vec(values: &[Self]) -> Value
+    where
+        Self: Sized,
+    {
+        Value::Array(values.iter().map(Describe::to_json_value).collect())
+    }
+}
+
+/// Decodes a `T` from `bytes` and renders it
+pub fn describe_bytes<T: Deserialize + Describe>(bytes: Vec<u8>) -> Result<Value> {
+    let value = T::deserialize(&mut Deserializer::new(BytesMut::from(bytes)))?;
+    Ok(value.to_json_value())
+}
+
+fn hex(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+macro_rules! impl_describe_number {
+    ($($t:ty),*) => {
+        $(
+            impl Describe for $t {
+                fn to_json_value(&self) -> Value {
+                    Value::from(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_describe_number!(u16, u32, u64, i8, i16, i32, i64, bool);
+
+impl Describe for u8 {
+    fn to_json_value(&self) -> Value {
+        Value::from(*self)
+    }
+
+    fn describe_vec(values: &[u8]) -> Value {
+        hex(values)
+    }
+}
+
+impl Describe for VarUint {
+    fn to_json_value(&self) -> Value {
+        Value::from(self.value)
+    }
+}
+
+impl Describe for String {
+    fn to_json_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl Describe for Sha256Result {
+    fn to_json_value(&self) -> Value {
+        hex(self)
+    }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+    fn to_json_value(&self) -> Value {
+        T::describe_vec(self)
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn to_json_value(&self) -> Value {
+        match self {
+            Some(x) => x.to_json_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::describe::{describe_bytes, Describe};
+    use crate::Sha256Result;
+    use serde_json::json;
+
+    #[test]
+    fn describe_values() {
+        assert_eq!(json!("00ff10"), vec![0_u8, 255, 16].to_json_value());
+        assert_eq!(json!([1, 2]), vec![1_u16, 2].to_json_value());
+        assert_eq!(json!(null), Option::<u32>::None.to_json_value());
+        assert_eq!(
+            json!("0101010101010101010101010101010101010101010101010101010101010101"),
+            Sha256Result::from([1; 32]).to_json_value()
+        );
+        assert_eq!(json!(-3), describe_bytes::<i8>(vec![0xFD]).unwrap());
+    }
+}
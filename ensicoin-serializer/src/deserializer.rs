@@ -1,8 +1,51 @@
+use super::serializer::Serialize;
+use super::types::Block;
+use super::types::BlockHeader;
+use super::types::BlockLocator;
+use super::types::CompactTarget;
+use super::types::DedupHashes;
+use super::types::FeeFilter;
+use super::types::Flagged;
+use super::types::InvVect;
+use super::types::NestedVec;
+use super::types::NetworkAddress;
+use super::types::Nonce;
+use super::types::OutPoint;
+use super::types::Ping;
+use super::types::Pong;
+use super::types::Reject;
+use super::types::Remainder;
+use super::types::Rle;
+use super::types::Script;
 use super::types::Sha256Result;
+use super::types::Timestamp;
+use super::types::TlvBlock;
+use super::types::Transaction;
+use super::types::TxInput;
+use super::types::TxOutput;
+use super::types::VarInt;
+use super::types::VarInt32;
 use super::types::VarUint;
-use bytes::BytesMut;
+use super::types::VersionMessage;
+use super::types::Work;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use bytes::{Bytes, BytesMut};
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem::{ManuallyDrop, MaybeUninit};
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::io::{self, Read};
 
-use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use core::ptr;
+use core::time::Duration;
 
 /// Errors possible when deserializing bytes
 
@@ -11,11 +54,21 @@ pub enum Error {
     Message(String),
     /// Typename, type size (0 being unknown), bytes read
     BufferTooShort(&'static str, usize, usize),
-    InvalidString(std::string::FromUtf8Error),
+    InvalidString(alloc::string::FromUtf8Error),
+    /// Length read, maximum allowed
+    LengthExceeded(u64, u64),
+    /// Value read, number of bytes it was written in although a shorter encoding exists
+    NonCanonicalVarUint(u64, usize),
+    /// Number of bytes left after a value that should have taken the whole input
+    TrailingBytes(usize),
+    /// Failure of the reader of a `Deserializer::from_reader`, an unexpected EOF when it ends
+    /// in the middle of a value
+    #[cfg(feature = "std")]
+    Io(io::Error),
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             Error::Message(s) => write!(f, "Error in deserializing : {}", s),
             Error::BufferTooShort(t, exp, bs) => write!(
@@ -24,155 +77,527 @@ impl std::fmt::Display for Error {
                 t, exp, bs
             ),
             Error::InvalidString(utf8err) => write!(f, "Invalid String: {}", utf8err),
+            Error::LengthExceeded(length, max) => {
+                write!(f, "Length {} exceeds the maximum of {}", length, max)
+            }
+            Error::NonCanonicalVarUint(value, length) => write!(
+                f,
+                "VarUint {} written in {} bytes instead of its shortest form",
+                value, length
+            ),
+            Error::TrailingBytes(n) => write!(f, "{} trailing bytes after the value", n),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::InvalidString(utf8err) => Some(utf8err),
+            #[cfg(feature = "std")]
+            Error::Io(e) => Some(e),
+            _ => None,
         }
     }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// What to do with set bits that have no name when decoding a bitmask of flags
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnknownBits {
+    /// Fail on any unknown bit
+    Strict,
+    /// Ignore unknown bits, so that flags added by newer peers don't break decoding
+    Lenient,
+}
+
+/// Variant of a tagged enum whose tag is unknown, kept with its undecoded payload
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawVariant {
+    pub tag: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Result of `Deserializer::deserialize_enum_with_fallback`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Fallback<T> {
+    /// The tag was known and its payload parsed
+    Known(T),
+    /// The tag was unknown and the rest of the frame was captured as is
+    Raw(RawVariant),
+}
+
+/// Position in a Deserializer saved by `Deserializer::checkpoint`
+#[derive(Clone)]
+pub struct Checkpoint {
+    position: usize,
+}
+
+/// Function reading a value from a Deserializer
+pub type Parser<T> = fn(&mut Deserializer) -> Result<T>;
+
+/// Callback receiving the type name, value and offset of each primitive read
+type Trace = Box<dyn FnMut(&'static str, u64, usize)>;
 
 /// Structure holding the data to be deserialized
 pub struct Deserializer {
-    buffer: BytesMut,
+    /// Whole input, the bytes before `position` having been read. Reads only move `position`
+    /// and bulk reads are O(1) slices of the input
+    input: Bytes,
+    position: usize,
+    trace: Option<Trace>,
+    /// Number of collections and strings allocated so far, and its limit
+    allocations: usize,
+    max_allocations: usize,
+    /// Maximum number of elements of a collection or bytes of a string
+    max_collection_len: usize,
+    /// Position from which the consumed bytes are captured, if capturing
+    capture_start: Option<usize>,
+    /// Stream the input is pulled from as it is read, for `from_reader`
+    #[cfg(feature = "std")]
+    reader: Option<Box<dyn Read>>,
 }
 
 impl Deserializer {
+    /// Default maximum number of collections and strings allocated while deserializing
+    pub const DEFAULT_MAX_ALLOCATIONS: usize = 1 << 20;
+
+    /// Default maximum length of a collection or string, the size of the largest message
+    pub const DEFAULT_MAX_COLLECTION_LEN: usize = 1 << 25;
+
     /// Creates a Deserializer from a bytes vector
     pub fn new(b: BytesMut) -> Deserializer {
-        Deserializer { buffer: b }
+        Deserializer::from_bytes(b.freeze())
+    }
+
+    /// Reads from an already frozen buffer without copying it. This is the zero copy constructor:
+    /// a `Bytes` shared with other readers is only reference counted
+    pub fn from_bytes(input: Bytes) -> Deserializer {
+        Deserializer {
+            input,
+            position: 0,
+            trace: None,
+            allocations: 0,
+            max_allocations: Deserializer::DEFAULT_MAX_ALLOCATIONS,
+            max_collection_len: Deserializer::DEFAULT_MAX_COLLECTION_LEN,
+            capture_start: None,
+            #[cfg(feature = "std")]
+            reader: None,
+        }
+    }
+
+    /// Reads from a borrowed buffer, without requiring the caller to build a `BytesMut` first.
+    /// `data` is copied once into the deserializer, as a `Bytes` can't borrow it; use
+    /// `from_bytes` to read a buffer without copying it
+    pub fn from_slice(data: &[u8]) -> Deserializer {
+        Deserializer::from_bytes(Bytes::from(data))
+    }
+
+    /// Reads from a stream, pulling from `reader` only the bytes needed by each read so that
+    /// nothing past the decoded values is consumed. A stream ending in the middle of a value is
+    /// an `Error::Io` of kind `UnexpectedEof` instead of a `BufferTooShort`. The bytes read are
+    /// kept for `consumed`, `checkpoint` and `capture_raw`, and `remaining` and `is_empty` only
+    /// count the bytes already pulled. Each read is a call to `reader`, which should be
+    /// buffered when small values are read from a file or socket
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Deserializer {
+        let mut de = Deserializer::from_bytes(Bytes::new());
+        de.reader = Some(Box::new(reader));
+        de
+    }
+
+    /// Calls `trace` with the type name, value and offset of every integer and `VarUint` read,
+    /// to follow how a message is decoded
+    pub fn with_trace<F: FnMut(&'static str, u64, usize) + 'static>(
+        mut self,
+        trace: F,
+    ) -> Deserializer {
+        self.trace = Some(Box::new(trace));
+        self
+    }
+
+    /// Fails once more than `max_allocations` collections and strings have been allocated, to
+    /// bound the work spent on messages made of many small collections that each fit their own
+    /// length limits
+    pub fn with_max_allocations(mut self, max_allocations: usize) -> Deserializer {
+        self.max_allocations = max_allocations;
+        self
+    }
+
+    /// Fails on collections of more than `max_collection_len` elements and strings of more
+    /// than `max_collection_len` bytes as soon as their length is read, without trusting it to
+    /// read or allocate anything
+    pub fn with_max_collection_len(mut self, max_collection_len: usize) -> Deserializer {
+        self.max_collection_len = max_collection_len;
+        self
+    }
+
+    /// Fails if a collection or string of `length` elements is longer than allowed
+    fn check_collection_len(&self, length: u64) -> Result<()> {
+        if length > self.max_collection_len as u64 {
+            return Err(Error::LengthExceeded(
+                length,
+                self.max_collection_len as u64,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Counts an allocation, failing if there were too many
+    fn allocate(&mut self) -> Result<()> {
+        self.allocations += 1;
+        if self.allocations > self.max_allocations {
+            return Err(Error::Message(format!(
+                "More than {} allocations",
+                self.max_allocations
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if `count` elements is more than allowed, or if `count` elements of at least
+    /// `element_size` bytes each can't fit in the rest of the buffer, before anything is
+    /// allocated for them. The product saturates so that a huge
+    /// count can't wrap around to a size that passes the check
+    fn check_min_size(
+        &mut self,
+        name: &'static str,
+        count: u64,
+        element_size: usize,
+    ) -> Result<()> {
+        self.check_collection_len(count)?;
+        let min_size = VarUint { value: count }
+            .saturating_mul(element_size as u64)
+            .value;
+        self.fill(usize::try_from(min_size).unwrap_or(usize::MAX))?;
+        if min_size > self.remaining() as u64 {
+            return Err(Error::BufferTooShort(
+                name,
+                usize::try_from(min_size).unwrap_or(usize::MAX),
+                self.remaining(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bytes read so far
+    pub fn consumed(&self) -> &[u8] {
+        &self.input[..self.position]
+    }
+
+    /// Starts capturing the bytes consumed from the current position, or stops capturing. A
+    /// relay can then forward the exact bytes of what it parsed with `captured_raw`, even when
+    /// they differ from serializing the parsed value again
+    pub fn capture_raw(&mut self, enabled: bool) {
+        self.capture_start = if enabled { Some(self.position) } else { None };
+    }
+
+    /// Bytes consumed since `capture_raw(true)`, or `None` when not capturing
+    pub fn captured_raw(&self) -> Option<Bytes> {
+        self.capture_start
+            .map(|start| self.input.slice(start, self.position.max(start)))
+    }
+
+    /// Pulls bytes from the reader, if any, until `n` bytes are left to read or the stream
+    /// ends, returning whether there are `n` bytes left
+    #[inline]
+    fn fill_up_to(&mut self, n: usize) -> Result<bool> {
+        #[cfg(feature = "std")]
+        {
+            if self.remaining() < n && self.reader.is_some() {
+                self.pull(n - self.remaining())?;
+            }
+        }
+        Ok(self.remaining() >= n)
+    }
+
+    /// Appends up to `missing` bytes from the reader to the input
+    #[cfg(feature = "std")]
+    fn pull(&mut self, missing: usize) -> Result<()> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        // Reading through `take` only allocates for bytes actually present in the stream
+        let mut data = Vec::new();
+        if let Err(e) = reader.by_ref().take(missing as u64).read_to_end(&mut data) {
+            return Err(Error::Io(e));
+        }
+        // The input grows in place when nothing else shares it
+        let mut input = match core::mem::replace(&mut self.input, Bytes::new()).try_mut() {
+            Ok(input) => input,
+            Err(input) => BytesMut::from(&input[..]),
+        };
+        input.extend_from_slice(&data);
+        self.input = input.freeze();
+        Ok(())
+    }
+
+    /// Pulls bytes from the reader, if any, until `n` bytes are left to read, its stream ending
+    /// before being an unexpected EOF. Without a reader, the caller reports the missing bytes
+    #[inline]
+    fn fill(&mut self, n: usize) -> Result<()> {
+        let filled = self.fill_up_to(n)?;
+        #[cfg(feature = "std")]
+        {
+            if !filled && self.reader.is_some() {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "stream ended {} bytes into a read of {}",
+                        self.remaining(),
+                        n
+                    ),
+                )));
+            }
+        }
+        let _ = filled;
+        Ok(())
+    }
+
+    /// Pulls the whole rest of the stream from the reader, if any
+    fn fill_to_end(&mut self) -> Result<()> {
+        self.fill_up_to(usize::MAX).map(|_| ())
+    }
+
+    /// Checks that the whole input has been read, for messages that must take all of it. A
+    /// `from_reader` deserializer reads its stream to the end to count the bytes left
+    pub fn finish(mut self) -> Result<()> {
+        self.fill_to_end()?;
+        if !self.is_empty() {
+            return Err(Error::TrailingBytes(self.remaining()));
+        }
+        Ok(())
+    }
+
+    /// Number of bytes left to read
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.input.len() - self.position
+    }
+
+    /// Whether the whole input has been read
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Bytes left to read
+    fn unread(&self) -> &[u8] {
+        &self.input[self.position..]
+    }
+
+    /// Number of bytes read so far, as an offset in the input
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Saves the current position, to come back to it with `restore`
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+        }
+    }
+
+    /// Goes back to the position saved in `checkpoint`, as if nothing had been read since. The
+    /// trace callback is not replayed
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.position;
+    }
+
+    fn split(&mut self, length: usize) -> Bytes {
+        let bytes = self.input.slice(self.position, self.position + length);
+        self.position += length;
+        bytes
+    }
+
+    #[inline]
+    fn trace(&mut self, name: &'static str, value: u64, offset: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace(name, value, offset)
+        }
     }
 
     pub fn extract_bytes(&mut self, length: usize) -> Result<BytesMut> {
-        let buff_length = self.buffer.len();
+        self.fill(length)?;
+        let buff_length = self.remaining();
         if length > buff_length {
             Err(Error::BufferTooShort("bytes", length, buff_length))
         } else {
-            let raw = self.buffer.split_to(length);
+            let raw = self.split(length);
             #[cfg(feature = "log")]
             {
                 debug!(
                     "extracted {:?} as bytes, remaining in buffer: {:?}",
                     &raw.to_vec(),
-                    &self.buffer.to_vec()
+                    &self.unread().to_vec()
                 );
             }
-            Ok(raw)
+            Ok(BytesMut::from(raw))
         }
     }
 
-    fn deserialize_u8(&mut self) -> Result<u8> {
-        let length = self.buffer.len();
-        if length < 1 {
-            Err(Error::BufferTooShort("u8", 1, length))
-        } else {
-            let raw = self.buffer.split_to(1);
-            #[cfg(feature = "log")]
-            {
-                debug!(
-                    "extracted {:?} as u8, remaining in buffer: {:?}",
-                    &raw.to_vec(),
-                    &self.buffer.to_vec()
-                );
-            }
-            Ok(raw[0])
+    /// Drops the next `n` bytes, to step over fields that are not understood without copying
+    /// them as `extract_bytes` does
+    pub fn skip(&mut self, n: usize) -> Result<()> {
+        self.fill(n)?;
+        if self.remaining() < n {
+            return Err(Error::BufferTooShort("bytes", n, self.remaining()));
         }
+        self.position += n;
+        Ok(())
     }
 
-    fn deserialize_u16(&mut self) -> Result<u16> {
-        let length = self.buffer.len();
-        if length < 2 {
-            Err(Error::BufferTooShort("u16", 2, length))
-        } else {
-            let raw = self.buffer.split_to(2);
-            #[cfg(feature = "log")]
-            {
-                debug!(
-                    "extracted {:?} as u16, remaining in buffer: {:?}",
-                    &raw.to_vec(),
-                    &self.buffer.to_vec()
-                );
-            }
-            Ok(((raw[0] as u16) << 8) + (raw[1] as u16))
+    /// Returns the next byte without consuming it, for lookahead on tags
+    pub fn peek_u8(&mut self) -> Result<u8> {
+        self.peek_bytes(1).map(|bytes| bytes[0])
+    }
+
+    /// Returns the next `n` bytes without consuming them
+    pub fn peek_bytes(&mut self, n: usize) -> Result<&[u8]> {
+        self.fill(n)?;
+        if self.remaining() < n {
+            return Err(Error::BufferTooShort("bytes", n, self.remaining()));
         }
+        Ok(&self.unread()[..n])
     }
 
-    fn deserialize_u32(&mut self) -> Result<u32> {
-        let length = self.buffer.len();
-        if length < 4 {
-            Err(Error::BufferTooShort("u32", 4, length))
-        } else {
-            let raw = self.buffer.split_to(4);
-            #[cfg(feature = "log")]
-            {
-                debug!(
-                    "extracted {:?} as u32, remaining in buffer: {:?}",
-                    &raw.to_vec(),
-                    &self.buffer.to_vec()
-                );
-            }
-            let mut value: u32 = 0;
-            for i in 1..=4 {
-                value |= (raw[i - 1] as u32) << 8 * (4 - i);
+    /// Reads a big endian unsigned integer of `size` bytes
+    #[inline]
+    fn read_uint(&mut self, name: &'static str, size: usize) -> Result<u64> {
+        self.fill(size)?;
+        match self.input.get(self.position..self.position + size) {
+            None => Err(Error::BufferTooShort(name, size, self.remaining())),
+            Some(raw) => {
+                let value = raw.iter().fold(0, |value, b| (value << 8) | *b as u64);
+                #[cfg(feature = "log")]
+                {
+                    debug!(
+                        "extracted {:?} as {}, remaining in buffer: {:?}",
+                        &self.unread()[..size],
+                        name,
+                        &self.unread()[size..]
+                    );
+                }
+                self.position += size;
+                Ok(value)
             }
-            Ok(value)
         }
     }
 
+    #[inline]
+    fn deserialize_u8(&mut self) -> Result<u8> {
+        let offset = self.position;
+        let value = self.read_uint("u8", 1)?;
+        self.trace("u8", value, offset);
+        Ok(value as u8)
+    }
+
+    #[inline]
+    fn deserialize_u16(&mut self) -> Result<u16> {
+        let offset = self.position;
+        let value = self.read_uint("u16", 2)?;
+        self.trace("u16", value, offset);
+        Ok(value as u16)
+    }
+
+    #[inline]
+    fn deserialize_u32(&mut self) -> Result<u32> {
+        let offset = self.position;
+        let value = self.read_uint("u32", 4)?;
+        self.trace("u32", value, offset);
+        Ok(value as u32)
+    }
+
+    #[inline]
     fn deserialize_u64(&mut self) -> Result<u64> {
-        let length = self.buffer.len();
-        if length < 8 {
-            Err(Error::BufferTooShort("u64", 8, length))
-        } else {
-            let raw = self.buffer.split_to(8);
-            #[cfg(feature = "log")]
-            {
-                debug!(
-                    "extracted {:?} as u64, remaining in buffer: {:?}",
-                    &raw.to_vec(),
-                    &self.buffer.to_vec()
-                );
-            }
-            let mut value: u64 = 0;
-            for i in 1..=8 {
-                value |= (raw[i - 1] as u64) << 8 * (8 - i);
-            }
-            Ok(value)
+        let offset = self.position;
+        let value = self.read_uint("u64", 8)?;
+        self.trace("u64", value, offset);
+        Ok(value)
+    }
+
+    fn deserialize_bool(&mut self) -> Result<bool> {
+        let offset = self.position;
+        let value = self.read_uint("bool", 1)?;
+        self.trace("bool", value, offset);
+        match value {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(Error::Message(format!(
+                "Invalid bool encoding {:#04x}, expected 0x00 or 0x01",
+                b
+            ))),
         }
     }
+
+    #[inline]
+    fn deserialize_i8(&mut self) -> Result<i8> {
+        let offset = self.position;
+        let value = self.read_uint("i8", 1)?;
+        self.trace("i8", value, offset);
+        Ok(value as i8)
+    }
+
+    #[inline]
+    fn deserialize_i16(&mut self) -> Result<i16> {
+        let offset = self.position;
+        let value = self.read_uint("i16", 2)?;
+        self.trace("i16", value, offset);
+        Ok(value as i16)
+    }
+
+    #[inline]
+    fn deserialize_i32(&mut self) -> Result<i32> {
+        let offset = self.position;
+        let value = self.read_uint("i32", 4)?;
+        self.trace("i32", value, offset);
+        Ok(value as i32)
+    }
+
+    #[inline]
+    fn deserialize_i64(&mut self) -> Result<i64> {
+        let offset = self.position;
+        let value = self.read_uint("i64", 8)?;
+        self.trace("i64", value, offset);
+        Ok(value as i64)
+    }
+
     fn deserialize_varuint(&mut self) -> Result<VarUint> {
-        let first_byte = match self.deserialize_u8() {
-            Ok(n) => n,
-            Err(Error::BufferTooShort(_, exp, len)) => {
-                return Err(Error::BufferTooShort("VarUint", exp, len));
-            }
-            Err(e) => return Err(e),
-        };
-        let value = match first_byte {
-            0xFD => match self.deserialize_u16() {
-                Ok(n) => n as u64,
-                Err(Error::BufferTooShort(_, exp, len)) => {
-                    return Err(Error::BufferTooShort("VarUint", exp, len));
-                }
-                Err(e) => return Err(e),
-            },
-            0xFE => match self.deserialize_u32() {
-                Ok(n) => n as u64,
-                Err(Error::BufferTooShort(_, exp, len)) => {
-                    return Err(Error::BufferTooShort("VarUint", exp, len));
-                }
-                Err(e) => return Err(e),
-            },
-            0xFF => match self.deserialize_u64() {
-                Ok(n) => n as u64,
-                Err(Error::BufferTooShort(_, exp, len)) => {
-                    return Err(Error::BufferTooShort("VarUint", exp, len));
-                }
-                Err(e) => return Err(e),
-            },
-            _ => first_byte as u64,
+        let offset = self.position;
+        let first_byte = self.read_uint("VarUint", 1)?;
+        // Each prefix must only be used for values that don't fit in the shorter ones, so that
+        // every number has a single encoding
+        let (length, min) = match first_byte {
+            0xFD => (2, 0xFD),
+            0xFE => (4, 0x10000),
+            0xFF => (8, 0x100000000),
+            _ => (0, 0),
+        };
+        let value = match length {
+            0 => first_byte,
+            _ => self.read_uint("VarUint", length)?,
         };
+        if value < min {
+            return Err(Error::NonCanonicalVarUint(value, 1 + length));
+        }
+        self.trace("VarUint", value, offset);
         Ok(VarUint { value })
     }
 
+    /// Reads a `VarUint` that must not be 0, for counts with a mandatory minimum of one element
+    pub fn deserialize_varuint_nonzero(&mut self) -> Result<VarUint> {
+        let n = self.deserialize_varuint()?;
+        if n.value == 0 {
+            Err(Error::Message("expected nonzero".to_string()))
+        } else {
+            Ok(n)
+        }
+    }
+
     fn deserialize_string(&mut self) -> Result<String> {
         let length = match self.deserialize_varuint() {
             Ok(n) => n.value as usize,
@@ -183,18 +608,21 @@ impl Deserializer {
                 )));
             }
         };
-        if self.buffer.len() < length {
-            Err(Error::BufferTooShort("String", length, self.buffer.len()))
+        self.check_collection_len(length as u64)?;
+        self.fill(length)?;
+        if self.remaining() < length {
+            Err(Error::BufferTooShort("String", length, self.remaining()))
         } else {
             #[cfg(feature = "log")]
             {
                 debug!(
                     "extracting {} bytes as string, remaining in buffer: {:?}",
                     length,
-                    &self.buffer.to_vec()
+                    &self.unread().to_vec()
                 );
             }
-            let bytes = self.buffer.split_to(length);
+            self.allocate()?;
+            let bytes = self.split(length);
             match String::from_utf8(bytes.to_vec()) {
                 Err(utf8err) => Err(Error::InvalidString(utf8err)),
                 Ok(s) => Ok(s),
@@ -202,218 +630,3144 @@ impl Deserializer {
         }
     }
 
-    pub fn deserialize_vec<T: Deserialize>(&mut self) -> Result<Vec<T>> {
+    /// Reads a length prefixed string that must be 7-bit ASCII, for command and agent names,
+    /// which is stricter and cheaper to check than UTF-8
+    pub fn deserialize_ascii_string(&mut self) -> Result<String> {
         let length = match self.deserialize_varuint() {
             Ok(n) => n.value as usize,
             Err(e) => {
                 return Err(Error::Message(format!(
-                    "Error in reading vec length: {}",
+                    "Error in reading string length: {}",
                     e
                 )));
             }
         };
-        #[cfg(feature = "log")]
-        {
-            debug!(
-                "extracting {} elements as vec, remaining in buffer: {:?}",
-                length,
-                &self.buffer.to_vec()
-            );
+        self.check_collection_len(length as u64)?;
+        self.fill(length)?;
+        if self.remaining() < length {
+            return Err(Error::BufferTooShort("String", length, self.remaining()));
         }
-        let mut v = Vec::new();
-        for i in 0..length {
-            v.push(match T::deserialize(self) {
-                Ok(x) => x,
-                Err(e) => {
-                    return Err(Error::Message(format!(
-                        "Error in reading vec item {}: {}",
-                        i, e
-                    )))
-                }
-            });
+        self.allocate()?;
+        let bytes = self.split(length);
+        match bytes.iter().position(|b| !b.is_ascii()) {
+            Some(i) => Err(Error::Message(format!(
+                "Non ASCII byte {:#x} at index {} of string",
+                bytes[i], i
+            ))),
+            None => Ok(bytes.iter().map(|b| *b as char).collect()),
         }
-        Ok(v)
     }
-}
-
-/// Trait used to create an instance of a type from a Deserializer
-pub trait Deserialize: Sized {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self>;
-}
 
-impl Deserialize for u8 {
-    fn deserialize(de: &mut Deserializer) -> Result<u8> {
-        de.deserialize_u8()
-    }
-}
-impl Deserialize for u16 {
-    fn deserialize(de: &mut Deserializer) -> Result<u16> {
-        de.deserialize_u16()
-    }
-}
-impl Deserialize for u32 {
-    fn deserialize(de: &mut Deserializer) -> Result<u32> {
-        de.deserialize_u32()
-    }
-}
-impl Deserialize for u64 {
-    fn deserialize(de: &mut Deserializer) -> Result<u64> {
-        de.deserialize_u64()
-    }
-}
-impl Deserialize for VarUint {
-    fn deserialize(de: &mut Deserializer) -> Result<VarUint> {
-        de.deserialize_varuint()
-    }
-}
-impl Deserialize for String {
-    fn deserialize(de: &mut Deserializer) -> Result<String> {
-        de.deserialize_string()
-    }
-}
-impl<T: Deserialize> Deserialize for Vec<T> {
-    fn deserialize(de: &mut Deserializer) -> Result<Vec<T>> {
-        de.deserialize_vec()
+    /// Reads a fixed slot of `n` bytes and strips the trailing zeros padding it, as written by
+    /// `serializer::serialize_padded`
+    pub fn deserialize_padded(&mut self, n: usize) -> Result<Vec<u8>> {
+        let mut bytes = self.extract_bytes(n)?.to_vec();
+        let length = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        bytes.truncate(length);
+        Ok(bytes)
     }
-}
 
-impl Deserialize for Sha256Result {
-    fn deserialize(de: &mut Deserializer) -> Result<Sha256Result> {
-        Ok(Sha256Result::clone_from_slice(&de.extract_bytes(32)?))
+    /// Reads `expected.len()` bytes and fails if they differ from `expected`, for formats ending
+    /// with a known trailer
+    pub fn expect_bytes(&mut self, expected: &[u8]) -> Result<()> {
+        let bytes = self.extract_bytes(expected.len())?;
+        if bytes != expected {
+            return Err(Error::Message(format!(
+                "unexpected trailer {:?}, expected {:?}",
+                &bytes[..],
+                expected
+            )));
+        }
+        Ok(())
     }
-}
 
-impl Deserialize for SocketAddr {
-    fn deserialize(de: &mut Deserializer) -> Result<SocketAddr> {
-        let high = match u64::deserialize(de) {
-            Ok(x) => x,
+    pub fn deserialize_vec<T: Deserialize>(&mut self) -> Result<Vec<T>> {
+        let count = match self.deserialize_varuint() {
+            Ok(n) => n.value,
             Err(e) => {
                 return Err(Error::Message(format!(
-                    "In reading SocketAddr ip high: {}",
+                    "Error in reading vec length: {}",
                     e
                 )));
             }
         };
-        let low = match u64::deserialize(de) {
-            Ok(x) => x,
+        self.deserialize_vec_items(count)
+    }
+
+    /// Reads a `Vec` whose elements must be in strictly increasing order
+    pub fn deserialize_vec_sorted<T: Deserialize + Ord>(&mut self) -> Result<Vec<T>> {
+        let v: Vec<T> = self.deserialize_vec()?;
+        match v.windows(2).position(|pair| pair[0] >= pair[1]) {
+            Some(i) => Err(Error::Message(format!(
+                "Element {} of sorted vec is not greater than the previous one",
+                i + 1
+            ))),
+            None => Ok(v),
+        }
+    }
+
+    /// Reads a `Vec` whose length is a fixed size `u32` instead of a `VarUint`, so that a
+    /// leading `0xFD`/`0xFE`/`0xFF` byte is never mistaken for a `VarUint` prefix
+    pub fn deserialize_vec_fixed_len_u32<T: Deserialize>(&mut self) -> Result<Vec<T>> {
+        let count = match self.deserialize_u32() {
+            Ok(n) => n as u64,
             Err(e) => {
                 return Err(Error::Message(format!(
-                    "In reading SocketAddr ip low: {}",
+                    "Error in reading vec length: {}",
                     e
                 )));
             }
         };
-        let addr = ((high as u128) << 64) + (low as u128);
-        let port = match u16::deserialize(de) {
-            Ok(x) => x,
-            Err(e) => return Err(Error::Message(format!("In reading SocketAddr port: {}", e))),
-        };
-        Ok(SocketAddr::new(IpAddr::from(Ipv6Addr::from(addr)), port))
+        self.deserialize_vec_items(count)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::deserializer::Deserialize;
-    use crate::deserializer::Deserializer;
-    extern crate bytes;
-    use bytes::BytesMut;
+    /// Reads a `length` bytes checksum, the start of the double SHA-256 of all the bytes read
+    /// so far, and fails if it does not match them
+    #[cfg(feature = "sha2")]
+    pub fn verify_trailing_checksum(&mut self, length: usize) -> Result<()> {
+        use sha2::{Digest, Sha256};
 
-    #[test]
-    fn deserialize_vec() {
-        let mut v = Vec::new();
-        v.push(2);
-        v.push(2);
-        v.push(42);
-        v.push(43);
+        if length > 32 {
+            return Err(Error::Message(format!(
+                "Checksum of {} bytes longer than a SHA-256",
+                length
+            )));
+        }
+        let hash = Sha256::digest(&Sha256::digest(self.consumed()));
+        let checksum = self.extract_bytes(length)?;
+        if checksum[..] != hash[..length] {
+            return Err(Error::Message(format!(
+                "Invalid checksum {:?}, expected {:?}",
+                &checksum[..],
+                &hash[..length]
+            )));
+        }
+        Ok(())
+    }
+
+    /// Tries each parser in order, starting from the same position, and returns the first
+    /// success. The position is restored after each failure, and the errors of all the parsers
+    /// are reported if none succeeds
+    pub fn deserialize_alt<T>(
+        &mut self,
+        parsers: &[fn(&mut Deserializer) -> Result<T>],
+    ) -> Result<T> {
+        let mut errors = Vec::new();
+        for parser in parsers {
+            let checkpoint = self.checkpoint();
+            match parser(self) {
+                Ok(x) => return Ok(x),
+                Err(e) => {
+                    errors.push(e.to_string());
+                    self.restore(checkpoint);
+                }
+            }
+        }
+        Err(Error::Message(format!(
+            "No alternative matched: [{}]",
+            errors.join(", ")
+        )))
+    }
+
+    /// Reads a `u64` bitmask and returns the flags whose bit is set, in the order of `mapping`,
+    /// which associates each flag to its single bit mask
+    pub fn deserialize_bitset_flags<T: Clone>(
+        &mut self,
+        mapping: &[(u64, T)],
+        unknown_bits: UnknownBits,
+    ) -> Result<Vec<T>> {
+        let mask = match self.deserialize_u64() {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("Error in reading flags: {}", e))),
+        };
+        let known = mapping.iter().fold(0, |known, (bit, _)| known | bit);
+        let unknown = mask & !known;
+        if unknown != 0 {
+            match unknown_bits {
+                UnknownBits::Strict => {
+                    return Err(Error::Message(format!("Unknown flag bits {:#x}", unknown)));
+                }
+                UnknownBits::Lenient => {
+                    #[cfg(feature = "log")]
+                    {
+                        warn!("ignoring unknown flag bits {:#x}", unknown);
+                    }
+                }
+            }
+        }
+        Ok(mapping
+            .iter()
+            .filter(|(bit, _)| mask & bit != 0)
+            .map(|(_, flag)| flag.clone())
+            .collect())
+    }
+
+    /// Reads elements until `keep` returns false or the buffer is empty, for lists ended by a
+    /// sentinel rather than a count. The element for which `keep` returns false is consumed but
+    /// not included in the result. An element cut by the end of the buffer is an error
+    pub fn deserialize_vec_while<T: Deserialize, F: Fn(&T) -> bool>(
+        &mut self,
+        keep: F,
+    ) -> Result<Vec<T>> {
+        self.allocate()?;
+        let mut v = Vec::new();
+        while self.fill_up_to(1)? {
+            let x = match T::deserialize(self) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Error in reading element {}: {}",
+                        v.len(),
+                        e
+                    )));
+                }
+            };
+            if !keep(&x) {
+                break;
+            }
+            v.push(x);
+        }
+        Ok(v)
+    }
+
+    /// Reads a map encoded as a `VarUint` count followed by its key value pairs, passing each
+    /// pair to `f` as it is decoded instead of collecting them
+    pub fn deserialize_map_streaming<K, V, F>(&mut self, mut f: F) -> Result<()>
+    where
+        K: Deserialize,
+        V: Deserialize,
+        F: FnMut(K, V) -> Result<()>,
+    {
+        let count = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading map length: {}",
+                    e
+                )));
+            }
+        };
+        self.check_min_size(
+            "Map",
+            count,
+            K::MIN_SERIALIZED_SIZE + V::MIN_SERIALIZED_SIZE,
+        )?;
+        for i in 0..count {
+            let key = match K::deserialize(self) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Error in reading map key {}: {}",
+                        i, e
+                    )));
+                }
+            };
+            let value = match V::deserialize(self) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Error in reading map value {}: {}",
+                        i, e
+                    )));
+                }
+            };
+            f(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a `VarUint` length prefix, failing with `Error::LengthExceeded` if it is above `max`
+    pub fn deserialize_varuint_bounded(&mut self, max: u64) -> Result<VarUint> {
+        let n = self.deserialize_varuint()?;
+        if n.value > max {
+            return Err(Error::LengthExceeded(n.value, max));
+        }
+        Ok(n)
+    }
+
+    /// Reads a length prefixed bytes vector, rejecting lengths above `max` before allocating
+    pub fn deserialize_bytes_vec_max(&mut self, max: usize) -> Result<Vec<u8>> {
+        let length = match self.deserialize_varuint_bounded(max as u64) {
+            Ok(n) => n.value,
+            Err(e @ Error::LengthExceeded(..)) => return Err(e),
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading bytes length: {}",
+                    e
+                )));
+            }
+        };
+        let bytes = self.extract_bytes(length as usize)?;
+        self.allocate()?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Reads only the length of a `Vec`, its elements being deserialized one by one as the
+    /// returned iterator is advanced. Elements left unread once the iterator is dropped stay in
+    /// the buffer
+    pub fn deserialize_vec_lazy<'a, T: Deserialize>(&'a mut self) -> Result<LazyVec<'a, T>> {
+        let count = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading vec length: {}",
+                    e
+                )));
+            }
+        };
+        self.check_min_size("Vec", count, T::MIN_SERIALIZED_SIZE)?;
+        Ok(LazyVec {
+            de: self,
+            index: 0,
+            count,
+            element: PhantomData,
+        })
+    }
+
+    fn deserialize_vec_items<T: Deserialize>(&mut self, count: u64) -> Result<Vec<T>> {
+        self.check_min_size("Vec", count, T::MIN_SERIALIZED_SIZE)?;
+        let length = count as usize;
+        #[cfg(feature = "log")]
+        {
+            debug!(
+                "extracting {} elements as vec, remaining in buffer: {:?}",
+                length,
+                &self.unread().to_vec()
+            );
+        }
+        self.allocate()?;
+        let mut v = Vec::new();
+        for i in 0..length {
+            v.push(match T::deserialize(self) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Error in reading vec item {}: {}",
+                        i, e
+                    )))
+                }
+            });
+        }
+        Ok(v)
+    }
+
+    /// Reads a `Vec` whose elements must take at most `max_bytes` in total, to bound the memory
+    /// used by lists of variable size elements. The count is checked like the one of a `Vec`,
+    /// then fails with `LengthExceeded` as soon as the elements read so far go over the limit
+    pub fn deserialize_vec_capped<T: Deserialize>(&mut self, max_bytes: usize) -> Result<Vec<T>> {
+        let count = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading vec length: {}",
+                    e
+                )));
+            }
+        };
+        self.check_min_size("Vec", count, T::MIN_SERIALIZED_SIZE)?;
+        let min_size = VarUint { value: count }
+            .saturating_mul(T::MIN_SERIALIZED_SIZE as u64)
+            .value;
+        if min_size > max_bytes as u64 {
+            return Err(Error::LengthExceeded(min_size, max_bytes as u64));
+        }
+        self.allocate()?;
+        let start = self.position;
+        let mut v = Vec::new();
+        for i in 0..count {
+            v.push(match T::deserialize(self) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Error in reading vec item {}: {}",
+                        i, e
+                    )))
+                }
+            });
+            let consumed = self.position - start;
+            if consumed > max_bytes {
+                return Err(Error::LengthExceeded(consumed as u64, max_bytes as u64));
+            }
+        }
+        Ok(v)
+    }
+
+    /// Reads a header then hands it to `f` to read the body it describes
+    pub fn deserialize_header_then<H, B, F>(&mut self, f: F) -> Result<(H, B)>
+    where
+        H: Deserialize,
+        F: FnOnce(&H, &mut Deserializer) -> Result<B>,
+    {
+        let header = match H::deserialize(self) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("Error in reading header: {}", e))),
+        };
+        let body = match f(&header, self) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("Error in reading body: {}", e))),
+        };
+        Ok((header, body))
+    }
+
+    /// Reads all the remaining bytes as the payload of the active enum variant, for message
+    /// enums whose payload length comes from the outer frame
+    pub fn deserialize_enum_payload(&mut self) -> Result<Remainder> {
+        self.fill_to_end()?;
+        let length = self.remaining();
+        Ok(Remainder(self.extract_bytes(length)?.to_vec()))
+    }
+
+    /// Reads a `VarUint` tag and parses the variant payload with the parser registered for it
+    /// in `parsers`. An unknown tag is not an error: the rest of the current frame is returned
+    /// as a `RawVariant`, so that variants added by newer peers can be skipped or forwarded.
+    /// This should only be used on a frame bounded deserializer, such as the one given by
+    /// `deserialize_sized`, as the whole remaining buffer is captured
+    pub fn deserialize_enum_with_fallback<T>(
+        &mut self,
+        parsers: &[(u64, Parser<T>)],
+    ) -> Result<Fallback<T>> {
+        let tag = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => return Err(Error::Message(format!("Error in reading enum tag: {}", e))),
+        };
+        match parsers.iter().find(|(t, _)| *t == tag) {
+            Some((_, parser)) => match parser(self) {
+                Ok(x) => Ok(Fallback::Known(x)),
+                Err(e) => Err(Error::Message(format!(
+                    "Error in reading enum variant {}: {}",
+                    tag, e
+                ))),
+            },
+            None => {
+                self.fill_to_end()?;
+                let length = self.remaining();
+                Ok(Fallback::Raw(RawVariant {
+                    tag,
+                    bytes: self.extract_bytes(length)?.to_vec(),
+                }))
+            }
+        }
+    }
+
+    /// Reads a `NestedVec` at most `max_depth` levels deep, without recursing
+    pub fn deserialize_nested_vec(&mut self, max_depth: usize) -> Result<NestedVec> {
+        // Lists being filled, with the number of children still to read in each
+        let root_count = self.deserialize_nested_count(1, max_depth)?;
+        let mut stack = vec![(Vec::new(), root_count)];
+        loop {
+            let depth = stack.len();
+            let (children, remaining) = stack.last_mut().unwrap();
+            if *remaining > 0 {
+                *remaining -= 1;
+                let count = self.deserialize_nested_count(depth + 1, max_depth)?;
+                stack.push((Vec::new(), count));
+                continue;
+            }
+            let node = NestedVec {
+                children: core::mem::take(children),
+            };
+            stack.pop();
+            match stack.last_mut() {
+                Some((parent, _)) => parent.push(node),
+                None => return Ok(node),
+            }
+        }
+    }
+
+    fn deserialize_nested_count(&mut self, depth: usize, max_depth: usize) -> Result<u64> {
+        if depth > max_depth {
+            return Err(Error::Message(format!(
+                "NestedVec deeper than {} levels",
+                max_depth
+            )));
+        }
+        self.allocate()?;
+        let count = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading NestedVec length at depth {}: {}",
+                    depth, e
+                )));
+            }
+        };
+        self.fill(count as usize)?;
+        if count > self.remaining() as u64 {
+            return Err(Error::BufferTooShort(
+                "NestedVec",
+                count as usize,
+                self.remaining(),
+            ));
+        }
+        Ok(count)
+    }
+
+    /// Reads a `VarUint` byte length then a `T` from exactly that many bytes, failing if `T`
+    /// does not consume them all
+    pub fn deserialize_sized<T: Deserialize>(&mut self) -> Result<T> {
+        let (x, unread) = self.deserialize_sized_inner()?;
+        if unread != 0 {
+            return Err(Error::Message(format!(
+                "{} unread bytes in sized value",
+                unread
+            )));
+        }
+        Ok(x)
+    }
+
+    /// Reads a `VarUint` byte length then a `T` from that many bytes, skipping the bytes `T`
+    /// does not consume, like fields appended by newer versions of a nested message
+    pub fn deserialize_sized_lenient<T: Deserialize>(&mut self) -> Result<T> {
+        self.deserialize_sized_inner().map(|(x, _)| x)
+    }
+
+    /// Decodes a sized value, returning it with its number of unread bytes
+    fn deserialize_sized_inner<T: Deserialize>(&mut self) -> Result<(T, usize)> {
+        self.deserialize_framed("sized value", |inner| {
+            let x = T::deserialize(inner)?;
+            Ok((x, inner.remaining()))
+        })
+    }
+
+    /// Reads a `VarUint` byte length and runs `f` on a Deserializer of that many bytes, which
+    /// shares the allocation count of this one
+    fn deserialize_framed<R, F>(&mut self, name: &'static str, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Deserializer) -> Result<R>,
+    {
+        let length = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading {} length: {}",
+                    name, e
+                )));
+            }
+        };
+        self.fill(length as usize)?;
+        if length > self.remaining() as u64 {
+            return Err(Error::BufferTooShort(
+                name,
+                length as usize,
+                self.remaining(),
+            ));
+        }
+        let mut inner = Deserializer::new(self.extract_bytes(length as usize)?);
+        inner.allocations = self.allocations;
+        inner.max_allocations = self.max_allocations;
+        inner.max_collection_len = self.max_collection_len;
+        let result = f(&mut inner);
+        self.allocations = inner.allocations;
+        match result {
+            Ok(x) => Ok(x),
+            Err(e) => Err(Error::Message(format!("Error in reading {}: {}", name, e))),
+        }
+    }
+
+    /// Reads a `Vec` prefixed by its `VarUint` length in bytes rather than its number of
+    /// elements, the elements filling exactly that many bytes
+    pub fn deserialize_vec_byte_prefixed<T: Deserialize>(&mut self) -> Result<Vec<T>> {
+        self.deserialize_framed("byte prefixed vec", |inner| {
+            inner.allocate()?;
+            let mut v = Vec::new();
+            while !inner.is_empty() {
+                match T::deserialize(inner) {
+                    Ok(x) => v.push(x),
+                    Err(e) => {
+                        return Err(Error::Message(format!(
+                            "Error in reading vec item {}: {}",
+                            v.len(),
+                            e
+                        )));
+                    }
+                }
+            }
+            Ok(v)
+        })
+    }
+
+    /// Reads a `Rle`, failing before allocating if it expands to more than `max_size` bytes
+    pub fn deserialize_rle(&mut self, max_size: usize) -> Result<Rle> {
+        let runs = match self.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => return Err(Error::Message(format!("In reading Rle runs: {}", e))),
+        };
+        self.check_min_size("Rle", runs, 2)?;
+        self.allocate()?;
+        let mut bytes = Vec::new();
+        for _ in 0..runs {
+            let count = match self.deserialize_varuint() {
+                Ok(n) => n.value,
+                Err(e) => return Err(Error::Message(format!("In reading Rle count: {}", e))),
+            };
+            if count > (max_size - bytes.len()) as u64 {
+                return Err(Error::Message(format!(
+                    "Rle expands to more than {} bytes",
+                    max_size
+                )));
+            }
+            let byte = match self.deserialize_u8() {
+                Ok(x) => x,
+                Err(e) => return Err(Error::Message(format!("In reading Rle byte: {}", e))),
+            };
+            bytes.resize(bytes.len() + count as usize, byte);
+        }
+        Ok(Rle(bytes))
+    }
+
+    /// Reads a `SocketAddr`, rejecting unspecified, loopback and multicast addresses that should
+    /// not be relayed to peers
+    pub fn deserialize_socketaddr_routable(&mut self) -> Result<SocketAddr> {
+        let addr = SocketAddr::deserialize(self)?;
+        let ip = addr.ip();
+        if ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() {
+            return Err(Error::Message(format!("Address {} is not routable", ip)));
+        }
+        Ok(addr)
+    }
+}
+
+/// Iterator over the elements of a `Vec` created by `Deserializer::deserialize_vec_lazy`,
+/// ending after the first error
+pub struct LazyVec<'a, T> {
+    de: &'a mut Deserializer,
+    index: u64,
+    count: u64,
+    element: PhantomData<T>,
+}
+
+impl<'a, T: Deserialize> Iterator for LazyVec<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.index >= self.count {
+            return None;
+        }
+        let item = match T::deserialize(self.de) {
+            Ok(x) => Ok(x),
+            Err(e) => {
+                self.count = self.index;
+                Err(Error::Message(format!(
+                    "Error in reading vec item {}: {}",
+                    self.index, e
+                )))
+            }
+        };
+        self.index += 1;
+        Some(item)
+    }
+}
+
+/// Trait used to create an instance of a type from a Deserializer
+pub trait Deserialize: Sized {
+    /// Lower bound on the number of bytes taken by any serialized value of this type, used to
+    /// reject collection lengths that can't fit in the remaining buffer
+    const MIN_SERIALIZED_SIZE: usize = 0;
+
+    fn deserialize(deserializer: &mut Deserializer) -> Result<Self>;
+}
+
+/// Deserializes a `T` that must take all of `bytes`, as written by `serializer::to_bytes`
+pub fn from_bytes<T: Deserialize>(bytes: Vec<u8>) -> Result<T> {
+    let mut de = Deserializer::from_bytes(Bytes::from(bytes));
+    let value = T::deserialize(&mut de)?;
+    de.finish()?;
+    Ok(value)
+}
+
+/// Trait used to create an instance of a type from a Deserializer and a context, i.e. data read
+/// earlier that the encoding of the type depends on
+pub trait DeserializeWithContext<C>: Sized {
+    fn deserialize_with_context(deserializer: &mut Deserializer, context: C) -> Result<Self>;
+}
+
+impl Deserialize for u8 {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<u8> {
+        de.deserialize_u8()
+    }
+}
+impl Deserialize for u16 {
+    const MIN_SERIALIZED_SIZE: usize = 2;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<u16> {
+        de.deserialize_u16()
+    }
+}
+impl Deserialize for u32 {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<u32> {
+        de.deserialize_u32()
+    }
+}
+impl Deserialize for u64 {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<u64> {
+        de.deserialize_u64()
+    }
+}
+
+impl Deserialize for bool {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<bool> {
+        de.deserialize_bool()
+    }
+}
+
+impl Deserialize for i8 {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<i8> {
+        de.deserialize_i8()
+    }
+}
+
+impl Deserialize for i16 {
+    const MIN_SERIALIZED_SIZE: usize = 2;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<i16> {
+        de.deserialize_i16()
+    }
+}
+
+impl Deserialize for i32 {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<i32> {
+        de.deserialize_i32()
+    }
+}
+
+impl Deserialize for i64 {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<i64> {
+        de.deserialize_i64()
+    }
+}
+
+impl Deserialize for f32 {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    fn deserialize(de: &mut Deserializer) -> Result<f32> {
+        de.deserialize_u32().map(f32::from_bits)
+    }
+}
+
+impl Deserialize for f64 {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<f64> {
+        de.deserialize_u64().map(f64::from_bits)
+    }
+}
+
+/// Surrogates and values above `char::MAX` are rejected, as they are not Unicode scalar values
+impl Deserialize for char {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    fn deserialize(de: &mut Deserializer) -> Result<char> {
+        let code_point = match de.deserialize_u32() {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading char: {}", e))),
+        };
+        match char::from_u32(code_point) {
+            Some(c) => Ok(c),
+            None => Err(Error::Message(format!(
+                "{:#x} is not a Unicode scalar value",
+                code_point
+            ))),
+        }
+    }
+}
+
+impl Deserialize for VarUint {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<VarUint> {
+        de.deserialize_varuint()
+    }
+}
+impl Deserialize for String {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<String> {
+        de.deserialize_string()
+    }
+}
+impl<T: Deserialize> Deserialize for Option<T> {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Option<T>> {
+        // Errors are passed through so that a truncated buffer stays a `BufferTooShort`
+        match de.deserialize_u8()? {
+            0 => Ok(None),
+            1 => T::deserialize(de).map(Some),
+            b => Err(Error::Message(format!(
+                "Invalid Option presence byte {}, expected 0 or 1",
+                b
+            ))),
+        }
+    }
+}
+
+impl<T: Deserialize> Deserialize for Vec<T> {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Vec<T>> {
+        de.deserialize_vec()
+    }
+}
+
+/// Reads exactly `N` elements, the length being known from the type
+impl<T: Deserialize, const N: usize> Deserialize for [T; N] {
+    const MIN_SERIALIZED_SIZE: usize = N * T::MIN_SERIALIZED_SIZE;
+
+    fn deserialize(de: &mut Deserializer) -> Result<[T; N]> {
+        /// Array filled in place, dropping the elements read so far if a later one fails
+        struct PartialArray<T, const N: usize> {
+            elements: [MaybeUninit<T>; N],
+            len: usize,
+        }
+
+        impl<T, const N: usize> Drop for PartialArray<T, N> {
+            fn drop(&mut self) {
+                for element in self.elements[..self.len].iter_mut() {
+                    // SAFETY: the first `len` elements have been written
+                    unsafe { element.assume_init_drop() }
+                }
+            }
+        }
+
+        let mut array = PartialArray {
+            elements: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        };
+        while array.len < N {
+            array.elements[array.len].write(T::deserialize(de)?);
+            array.len += 1;
+        }
+        let array = ManuallyDrop::new(array);
+        // SAFETY: the `N` elements have been written, and `array` won't drop them as it is
+        // wrapped in `ManuallyDrop`
+        Ok(unsafe { ptr::read(array.elements.as_ptr() as *const [T; N]) })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Deserialize + Eq + Hash, S: BuildHasher + Default> Deserialize for HashSet<T, S> {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<HashSet<T, S>> {
+        Ok(de.deserialize_vec::<T>()?.into_iter().collect())
+    }
+}
+
+impl<T: Deserialize + Ord> Deserialize for BTreeSet<T> {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<BTreeSet<T>> {
+        Ok(de.deserialize_vec::<T>()?.into_iter().collect())
+    }
+}
+
+/// Reads the entries of a map, passing them to `insert`. Keys must appear in strictly increasing
+/// order of their serialized bytes, as written by the `Serialize` impls, so that each map has a
+/// single valid encoding
+fn deserialize_map<K, V, F>(de: &mut Deserializer, mut insert: F) -> Result<()>
+where
+    K: Deserialize + Serialize,
+    V: Deserialize,
+    F: FnMut(K, V),
+{
+    let length = match de.deserialize_varuint() {
+        Ok(n) => n.value,
+        Err(e) => {
+            return Err(Error::Message(format!(
+                "Error in reading map length: {}",
+                e
+            )));
+        }
+    };
+    de.check_min_size(
+        "Map",
+        length,
+        K::MIN_SERIALIZED_SIZE + V::MIN_SERIALIZED_SIZE,
+    )?;
+    de.allocate()?;
+    let mut previous_key = None;
+    for i in 0..length {
+        let key = match K::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading map key {}: {}",
+                    i, e
+                )))
+            }
+        };
+        let value = match V::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "Error in reading map value {}: {}",
+                    i, e
+                )))
+            }
+        };
+        let raw_key = key.serialize();
+        if let Some(previous_key) = previous_key {
+            if raw_key <= previous_key {
+                return Err(Error::Message("non-canonical map ordering".to_string()));
+            }
+        }
+        previous_key = Some(raw_key);
+        insert(key, value);
+    }
+    Ok(())
+}
+
+impl<K: Deserialize + Serialize + Ord, V: Deserialize> Deserialize for BTreeMap<K, V> {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<BTreeMap<K, V>> {
+        let mut map = BTreeMap::new();
+        deserialize_map(de, |k, v| {
+            map.insert(k, v);
+        })?;
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, S> Deserialize for HashMap<K, V, S>
+where
+    K: Deserialize + Serialize + Eq + Hash,
+    V: Deserialize,
+    S: BuildHasher + Default,
+{
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<HashMap<K, V, S>> {
+        let mut map = HashMap::default();
+        deserialize_map(de, |k, v| {
+            map.insert(k, v);
+        })?;
+        Ok(map)
+    }
+}
+
+/// Tuples are read as their elements in order, with no length prefix
+macro_rules! impl_deserialize_tuple {
+    ($($t:ident: $i:tt),+) => {
+        impl<$($t: Deserialize),+> Deserialize for ($($t,)+) {
+            const MIN_SERIALIZED_SIZE: usize = 0 $(+ $t::MIN_SERIALIZED_SIZE)+;
+
+            fn deserialize(de: &mut Deserializer) -> Result<($($t,)+)> {
+                Ok(($(
+                    match $t::deserialize(de) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            return Err(Error::Message(format!(
+                                "In reading tuple element {}: {}",
+                                $i, e
+                            )));
+                        }
+                    },
+                )+))
+            }
+        }
+    };
+}
+
+impl_deserialize_tuple!(A: 0);
+impl_deserialize_tuple!(A: 0, B: 1);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+impl_deserialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+
+impl Deserialize for Sha256Result {
+    const MIN_SERIALIZED_SIZE: usize = 32;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Sha256Result> {
+        Ok(Sha256Result::clone_from_slice(&de.extract_bytes(32)?))
+    }
+}
+
+impl Deserialize for Ipv4Addr {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Ipv4Addr> {
+        match u32::deserialize(de) {
+            Ok(x) => Ok(Ipv4Addr::from(x)),
+            Err(e) => Err(Error::Message(format!("In reading Ipv4Addr: {}", e))),
+        }
+    }
+}
+
+impl Deserialize for IpAddr {
+    const MIN_SERIALIZED_SIZE: usize = 5;
+
+    fn deserialize(de: &mut Deserializer) -> Result<IpAddr> {
+        let family = match u8::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading IpAddr family: {}", e))),
+        };
+        match family {
+            4 => match Ipv4Addr::deserialize(de) {
+                Ok(ip) => Ok(IpAddr::V4(ip)),
+                Err(e) => Err(Error::Message(format!("In reading IpAddr: {}", e))),
+            },
+            6 => match de.extract_bytes(16) {
+                Ok(bytes) => {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(&bytes);
+                    Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+                }
+                Err(e) => Err(Error::Message(format!("In reading IpAddr: {}", e))),
+            },
+            _ => Err(Error::Message(format!("Unknown IpAddr family {}", family))),
+        }
+    }
+}
+
+impl Deserialize for SocketAddr {
+    const MIN_SERIALIZED_SIZE: usize = 18;
+
+    fn deserialize(de: &mut Deserializer) -> Result<SocketAddr> {
+        let high = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading SocketAddr ip high: {}",
+                    e
+                )));
+            }
+        };
+        let low = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading SocketAddr ip low: {}",
+                    e
+                )));
+            }
+        };
+        let addr = ((high as u128) << 64) + (low as u128);
+        let port = match u16::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading SocketAddr port: {}", e))),
+        };
+        let ip = Ipv6Addr::from(addr);
+        match ip.to_ipv4_mapped() {
+            Some(v4) => Ok(SocketAddr::new(IpAddr::V4(v4), port)),
+            None => Ok(SocketAddr::new(IpAddr::V6(ip), port)),
+        }
+    }
+}
+
+impl Deserialize for OutPoint {
+    const MIN_SERIALIZED_SIZE: usize = 36;
+
+    fn deserialize(de: &mut Deserializer) -> Result<OutPoint> {
+        let tx_hash = match Sha256Result::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading OutPoint tx_hash: {}",
+                    e
+                )));
+            }
+        };
+        let index = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading OutPoint index: {}", e))),
+        };
+        Ok(OutPoint { tx_hash, index })
+    }
+}
+
+impl Deserialize for InvVect {
+    const MIN_SERIALIZED_SIZE: usize = 36;
+
+    fn deserialize(de: &mut Deserializer) -> Result<InvVect> {
+        let kind = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading InvVect kind: {}", e))),
+        };
+        let hash = match Sha256Result::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading InvVect hash: {}", e))),
+        };
+        Ok(InvVect { kind, hash })
+    }
+}
+
+impl Deserialize for NestedVec {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<NestedVec> {
+        de.deserialize_nested_vec(NestedVec::DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl Deserialize for BlockHeader {
+    const MIN_SERIALIZED_SIZE: usize = 88;
+
+    fn deserialize(de: &mut Deserializer) -> Result<BlockHeader> {
+        let version = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader version: {}",
+                    e
+                )));
+            }
+        };
+        let prev_block = match Sha256Result::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader prev_block: {}",
+                    e
+                )));
+            }
+        };
+        let merkle_root = match Sha256Result::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader merkle_root: {}",
+                    e
+                )));
+            }
+        };
+        let timestamp = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader timestamp: {}",
+                    e
+                )));
+            }
+        };
+        let bits = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader bits: {}",
+                    e
+                )));
+            }
+        };
+        let nonce = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockHeader nonce: {}",
+                    e
+                )));
+            }
+        };
+        Ok(BlockHeader {
+            version,
+            prev_block,
+            merkle_root,
+            timestamp,
+            bits,
+            nonce,
+        })
+    }
+}
+
+impl Deserialize for Script {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Script> {
+        Ok(Script(de.deserialize_vec()?))
+    }
+}
+
+impl Deserialize for NetworkAddress {
+    const MIN_SERIALIZED_SIZE: usize = 30;
+
+    fn deserialize(de: &mut Deserializer) -> Result<NetworkAddress> {
+        let timestamp = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading NetworkAddress timestamp: {}",
+                    e
+                )));
+            }
+        };
+        let services = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading NetworkAddress services: {}",
+                    e
+                )));
+            }
+        };
+        let addr = match SocketAddr::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading NetworkAddress addr: {}",
+                    e
+                )));
+            }
+        };
+        Ok(NetworkAddress {
+            timestamp,
+            services,
+            addr,
+        })
+    }
+}
+
+impl Deserialize for CompactTarget {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    fn deserialize(de: &mut Deserializer) -> Result<CompactTarget> {
+        Ok(CompactTarget(de.deserialize_u32()?))
+    }
+}
+
+impl Deserialize for Remainder {
+    fn deserialize(de: &mut Deserializer) -> Result<Remainder> {
+        de.deserialize_enum_payload()
+    }
+}
+
+impl<T: Deserialize> DeserializeWithContext<bool> for Flagged<T> {
+    fn deserialize_with_context(de: &mut Deserializer, flag: bool) -> Result<Flagged<T>> {
+        if flag {
+            Ok(Flagged(Some(T::deserialize(de)?)))
+        } else {
+            Ok(Flagged(None))
+        }
+    }
+}
+
+impl Deserialize for TxInput {
+    const MIN_SERIALIZED_SIZE: usize = 41;
+
+    fn deserialize(de: &mut Deserializer) -> Result<TxInput> {
+        let previous_output = match OutPoint::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading TxInput previous_output: {}",
+                    e
+                )));
+            }
+        };
+        let script = match Script::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading TxInput script: {}", e)));
+            }
+        };
+        let sequence = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading TxInput sequence: {}",
+                    e
+                )));
+            }
+        };
+        Ok(TxInput {
+            previous_output,
+            script,
+            sequence,
+        })
+    }
+}
+
+impl Deserialize for TxOutput {
+    const MIN_SERIALIZED_SIZE: usize = 9;
+
+    fn deserialize(de: &mut Deserializer) -> Result<TxOutput> {
+        let value = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading TxOutput value: {}", e)));
+            }
+        };
+        let script = match Script::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading TxOutput script: {}", e)));
+            }
+        };
+        Ok(TxOutput { value, script })
+    }
+}
+
+impl Deserialize for Transaction {
+    const MIN_SERIALIZED_SIZE: usize = 15;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Transaction> {
+        let version = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Transaction version: {}",
+                    e
+                )));
+            }
+        };
+        let flags = match Vec::<String>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Transaction flags: {}",
+                    e
+                )));
+            }
+        };
+        let inputs = match Vec::<TxInput>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Transaction inputs: {}",
+                    e
+                )));
+            }
+        };
+        let outputs = match Vec::<TxOutput>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Transaction outputs: {}",
+                    e
+                )));
+            }
+        };
+        let locktime = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Transaction locktime: {}",
+                    e
+                )));
+            }
+        };
+        Ok(Transaction {
+            version,
+            flags,
+            inputs,
+            outputs,
+            locktime,
+        })
+    }
+}
+
+impl Deserialize for Rle {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Rle> {
+        de.deserialize_rle(Rle::DEFAULT_MAX_SIZE)
+    }
+}
+
+impl Deserialize for Nonce {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Nonce> {
+        let mut nonce = [0; 8];
+        nonce.copy_from_slice(&de.extract_bytes(8)?);
+        Ok(Nonce(nonce))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Deserialize for chrono::DateTime<chrono::Utc> {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+
+        let seconds = match i64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading DateTime: {}", e))),
+        };
+        match chrono::Utc.timestamp_opt(seconds, 0).single() {
+            Some(datetime) => Ok(datetime),
+            None => Err(Error::Message(format!(
+                "Timestamp {} out of the DateTime range",
+                seconds
+            ))),
+        }
+    }
+}
+
+impl Deserialize for TlvBlock {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<TlvBlock> {
+        let count = match de.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => return Err(Error::Message(format!("In reading TlvBlock length: {}", e))),
+        };
+        // Each entry takes at least a tag and an empty value
+        de.check_min_size("TlvBlock", count, 5)?;
+        let mut block = TlvBlock::new();
+        for _ in 0..count {
+            let tag = match u32::deserialize(de) {
+                Ok(x) => x,
+                Err(e) => return Err(Error::Message(format!("In reading TlvBlock tag: {}", e))),
+            };
+            let value = match Vec::<u8>::deserialize(de) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "In reading TlvBlock value of tag {}: {}",
+                        tag, e
+                    )));
+                }
+            };
+            if block.insert(tag, value).is_some() {
+                return Err(Error::Message(format!("Duplicate tag {} in TlvBlock", tag)));
+            }
+        }
+        Ok(block)
+    }
+}
+
+impl Deserialize for VarInt {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<VarInt> {
+        match VarUint::deserialize(de) {
+            Ok(n) => Ok(VarInt::from_zigzag(n.value)),
+            Err(e) => Err(Error::Message(format!("In reading VarInt: {}", e))),
+        }
+    }
+}
+
+impl Deserialize for VarInt32 {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<VarInt32> {
+        let zigzag = match VarUint::deserialize(de) {
+            Ok(n) => n.value,
+            Err(e) => return Err(Error::Message(format!("In reading VarInt32: {}", e))),
+        };
+        match u32::try_from(zigzag) {
+            Ok(zigzag) => Ok(VarInt32::from_zigzag(zigzag)),
+            Err(_) => Err(Error::Message(format!(
+                "VarInt32 {} out of the i32 range",
+                zigzag
+            ))),
+        }
+    }
+}
+
+impl Deserialize for BlockLocator {
+    const MIN_SERIALIZED_SIZE: usize = 33;
+
+    fn deserialize(de: &mut Deserializer) -> Result<BlockLocator> {
+        let hashes = match Vec::<Sha256Result>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockLocator hashes: {}",
+                    e
+                )));
+            }
+        };
+        let stop = match Sha256Result::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading BlockLocator stop: {}",
+                    e
+                )));
+            }
+        };
+        Ok(BlockLocator { hashes, stop })
+    }
+}
+
+impl Deserialize for Work {
+    const MIN_SERIALIZED_SIZE: usize = 16;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Work> {
+        let bytes = de.extract_bytes(16)?;
+        Ok(Work(bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u128)))
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl Deserialize for rust_decimal::Decimal {
+    const MIN_SERIALIZED_SIZE: usize = 16;
+
+    fn deserialize(de: &mut Deserializer) -> Result<rust_decimal::Decimal> {
+        let mut words = [0; 4];
+        for word in words.iter_mut() {
+            *word = match u32::deserialize(de) {
+                Ok(x) => x,
+                Err(e) => return Err(Error::Message(format!("In reading Decimal: {}", e))),
+            };
+        }
+        let [flags, high, middle, low] = words;
+        let scale = (flags >> 16) & 0xFF;
+        if flags & 0x7F00_FFFF != 0 {
+            return Err(Error::Message(format!(
+                "Invalid Decimal flags {:#x}",
+                flags
+            )));
+        }
+        if scale > rust_decimal::Decimal::MAX_SCALE {
+            return Err(Error::Message(format!(
+                "Decimal scale {} above the maximum of {}",
+                scale,
+                rust_decimal::Decimal::MAX_SCALE
+            )));
+        }
+        Ok(rust_decimal::Decimal::from_parts(
+            low,
+            middle,
+            high,
+            flags >> 31 == 1,
+            scale,
+        ))
+    }
+}
+
+impl Deserialize for DedupHashes {
+    const MIN_SERIALIZED_SIZE: usize = 1;
+
+    fn deserialize(de: &mut Deserializer) -> Result<DedupHashes> {
+        let count = match de.deserialize_varuint() {
+            Ok(n) => n.value,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading DedupHashes length: {}",
+                    e
+                )));
+            }
+        };
+        de.check_min_size("DedupHashes", count, 1)?;
+        de.allocate()?;
+        // Hashes in order of first appearance, indexed by the references, and searched through
+        // `seen` to keep decoding linear in the number of hashes
+        let mut table: Vec<Sha256Result> = Vec::new();
+        let mut seen: BTreeSet<Sha256Result> = BTreeSet::new();
+        let mut hashes = Vec::new();
+        for i in 0..count {
+            let reference = match de.deserialize_varuint() {
+                Ok(n) => n.value,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "In reading DedupHashes entry {}: {}",
+                        i, e
+                    )));
+                }
+            };
+            if reference == 0 {
+                let hash = match Sha256Result::deserialize(de) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return Err(Error::Message(format!(
+                            "In reading DedupHashes hash {}: {}",
+                            i, e
+                        )));
+                    }
+                };
+                if !seen.insert(hash) {
+                    return Err(Error::Message(format!(
+                        "Repeated hash {} written in full in DedupHashes",
+                        i
+                    )));
+                }
+                table.push(hash);
+                hashes.push(hash);
+            } else {
+                match table.get((reference - 1) as usize) {
+                    Some(hash) => hashes.push(*hash),
+                    None => {
+                        return Err(Error::Message(format!(
+                            "Reference {} to an unknown hash in DedupHashes",
+                            reference
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(DedupHashes(hashes))
+    }
+}
+
+impl Deserialize for FeeFilter {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<FeeFilter> {
+        match u64::deserialize(de) {
+            Ok(x) => Ok(FeeFilter(x)),
+            Err(e) => Err(Error::Message(format!("In reading FeeFilter: {}", e))),
+        }
+    }
+}
+
+impl Deserialize for Timestamp {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Timestamp> {
+        match u64::deserialize(de) {
+            Ok(x) => Ok(Timestamp(x)),
+            Err(e) => Err(Error::Message(format!("In reading Timestamp: {}", e))),
+        }
+    }
+}
+
+/// Nanoseconds must be less than a second, so that each duration has a single encoding
+impl Deserialize for Duration {
+    const MIN_SERIALIZED_SIZE: usize = 12;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Duration> {
+        let seconds = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Duration seconds: {}",
+                    e
+                )))
+            }
+        };
+        let nanos = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => return Err(Error::Message(format!("In reading Duration nanos: {}", e))),
+        };
+        if nanos >= 1_000_000_000 {
+            return Err(Error::Message(format!(
+                "Duration nanos {} not less than a second",
+                nanos
+            )));
+        }
+        Ok(Duration::new(seconds, nanos))
+    }
+}
+
+impl Deserialize for Ping {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Ping> {
+        match u64::deserialize(de) {
+            Ok(x) => Ok(Ping(x)),
+            Err(e) => Err(Error::Message(format!("In reading Ping: {}", e))),
+        }
+    }
+}
+
+impl Deserialize for Pong {
+    const MIN_SERIALIZED_SIZE: usize = 8;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Pong> {
+        match u64::deserialize(de) {
+            Ok(x) => Ok(Pong(x)),
+            Err(e) => Err(Error::Message(format!("In reading Pong: {}", e))),
+        }
+    }
+}
+
+impl Deserialize for Reject {
+    const MIN_SERIALIZED_SIZE: usize = 4;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Reject> {
+        let message = match String::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading Reject message: {}", e)));
+            }
+        };
+        let code = match u8::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading Reject code: {}", e)));
+            }
+        };
+        let reason = match String::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading Reject reason: {}", e)));
+            }
+        };
+        let data = match Option::<Sha256Result>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading Reject data: {}", e)));
+            }
+        };
+        Ok(Reject {
+            message,
+            code,
+            reason,
+            data,
+        })
+    }
+}
+
+impl Deserialize for VersionMessage {
+    const MIN_SERIALIZED_SIZE: usize = 70;
+
+    fn deserialize(de: &mut Deserializer) -> Result<VersionMessage> {
+        let version = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage version: {}",
+                    e
+                )));
+            }
+        };
+        let services = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage services: {}",
+                    e
+                )));
+            }
+        };
+        let timestamp = match u64::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage timestamp: {}",
+                    e
+                )));
+            }
+        };
+        let addr_recv = match SocketAddr::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage addr_recv: {}",
+                    e
+                )));
+            }
+        };
+        let addr_from = match SocketAddr::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage addr_from: {}",
+                    e
+                )));
+            }
+        };
+        let nonce = match Nonce::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage nonce: {}",
+                    e
+                )));
+            }
+        };
+        let user_agent = match String::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage user_agent: {}",
+                    e
+                )));
+            }
+        };
+        let start_height = match u32::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage start_height: {}",
+                    e
+                )));
+            }
+        };
+        let relay = match bool::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading VersionMessage relay: {}",
+                    e
+                )));
+            }
+        };
+        Ok(VersionMessage {
+            version,
+            services,
+            timestamp,
+            addr_recv,
+            addr_from,
+            nonce,
+            user_agent,
+            start_height,
+            relay,
+        })
+    }
+}
+
+/// The merkle root of the transactions isn't checked, see `Block::validate`
+impl Deserialize for Block {
+    const MIN_SERIALIZED_SIZE: usize = 89;
+
+    fn deserialize(de: &mut Deserializer) -> Result<Block> {
+        let header = match BlockHeader::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!("In reading Block header: {}", e)));
+            }
+        };
+        let transactions = match Vec::<Transaction>::deserialize(de) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Error::Message(format!(
+                    "In reading Block transactions: {}",
+                    e
+                )));
+            }
+        };
+        Ok(Block {
+            header,
+            transactions,
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unnecessary_cast, clippy::vec_init_then_push)]
+mod tests {
+    use crate::deserializer::Deserialize;
+    use crate::deserializer::DeserializeWithContext;
+    use crate::deserializer::Deserializer;
+    use crate::serializer::Serialize;
+    use crate::types::BlockHeader;
+    use crate::types::Flagged;
+    use crate::types::NestedVec;
+    use crate::types::Ping;
+    use crate::types::Pong;
+    use crate::types::Reject;
+    use crate::types::Remainder;
+    use crate::types::Rle;
+    use crate::Sha256Result;
+    extern crate bytes;
+    use bytes::BytesMut;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    #[cfg(feature = "std")]
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::rc::Rc;
+
+    #[test]
+    fn deserialize_vec() {
+        let mut v = Vec::new();
+        v.push(2);
+        v.push(2);
+        v.push(42);
+        v.push(43);
         v.push(1);
         v.push(44);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
-        };
+        let mut de = Deserializer::new(BytesMut::from(v));
         let decoded: Vec<Vec<u8>> = Vec::deserialize(&mut de).unwrap();
         assert_eq!(vec![vec![42 as u8, 43 as u8], vec![44]], decoded);
     }
 
     #[test]
-    fn deserialize_string() {
-        let mut v = Vec::new();
-        v.push(3);
-        v.push(97);
-        v.push(98);
-        v.push(99);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
-        };
-        let decoded = String::deserialize(&mut de).unwrap();
-        assert_eq!(String::from("abc"), decoded);
+    fn deserialize_string() {
+        let mut v = Vec::new();
+        v.push(3);
+        v.push(97);
+        v.push(98);
+        v.push(99);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = String::deserialize(&mut de).unwrap();
+        assert_eq!(String::from("abc"), decoded);
+    }
+
+    #[test]
+    fn deserialize_varuint() {
+        let mut v = Vec::new();
+        v.push(0xFD as u8);
+        v.push(42);
+        v.push(43);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = de.deserialize_varuint().unwrap();
+        assert_eq!(10795, decoded.value);
+    }
+
+    #[test]
+    fn deserialize_u64() {
+        let mut v = Vec::new();
+        v.push(42);
+        v.push(43);
+        v.push(44);
+        v.push(45);
+        v.push(46);
+        v.push(47);
+        v.push(48);
+        v.push(49);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = de.deserialize_u64().unwrap();
+        assert_eq!(3038570946151526449, decoded);
+    }
+
+    #[test]
+    fn deserialize_u32() {
+        let mut v = Vec::new();
+        v.push(42);
+        v.push(43);
+        v.push(44);
+        v.push(45);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = de.deserialize_u32().unwrap();
+        assert_eq!(707472429, decoded);
+    }
+
+    #[test]
+    fn deserialize_u8() {
+        let mut v = Vec::new();
+        v.push(125);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = de.deserialize_u8().unwrap();
+        assert_eq!(125, decoded);
+    }
+
+    #[test]
+    fn deserialize_u16() {
+        let mut v = Vec::new();
+        v.push(10);
+        v.push(15);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = de.deserialize_u16().unwrap();
+        assert_eq!(2575, decoded);
+    }
+
+    #[test]
+    fn deserialize_vec_impossible_length() {
+        let v = vec![3, 0, 0, 0, 1];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        match Vec::<u32>::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort("Vec", 12, 4)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("impossible length was accepted"),
+        }
+    }
+
+    #[test]
+    fn deserialize_vec_length_overflow() {
+        // 2^61 + 1 elements of 8 bytes wrap around to 8 bytes when multiplied without saturating
+        let mut v = vec![0xFF, 0x20, 0, 0, 0, 0, 0, 0, 1];
+        v.extend_from_slice(&[0; 8]);
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(usize::MAX);
+        match Vec::<u64>::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort("Vec", _, 8)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("overflowing length was accepted"),
+        }
+    }
+
+    #[test]
+    fn deserialize_map_length_overflow() {
+        // 2^60 + 1 pairs of 16 bytes wrap around to 16 bytes
+        let mut v = vec![0xFF, 0x10, 0, 0, 0, 0, 0, 0, 1];
+        v.extend_from_slice(&[0; 16]);
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(usize::MAX);
+        match BTreeMap::<u64, u64>::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort("Map", _, 16)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("overflowing length was accepted"),
+        }
+    }
+
+    #[test]
+    fn deserialize_rle_length_overflow() {
+        // 2^63 + 1 runs of 2 bytes wrap around to 2 bytes
+        let v = vec![0xFF, 0x80, 0, 0, 0, 0, 0, 0, 1, 1, 0];
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(usize::MAX);
+        match Rle::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort("Rle", _, 2)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("overflowing length was accepted"),
+        }
+    }
+
+    #[test]
+    fn deserialize_tlv_block_length_overflow() {
+        // 0xCCCCCCCCCCCCCCCD entries of 5 bytes wrap around to 1 byte
+        let mut v = vec![0xFF, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCD];
+        v.extend_from_slice(&[0, 0, 0, 1, 0]);
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(usize::MAX);
+        match crate::types::TlvBlock::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort("TlvBlock", _, 5)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("overflowing length was accepted"),
+        }
+    }
+
+    #[test]
+    fn deserialize_nested_vec() {
+        let v = vec![2, 0, 1, 0];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded = NestedVec::deserialize(&mut de).unwrap();
+        let expected = NestedVec {
+            children: vec![
+                NestedVec::default(),
+                NestedVec {
+                    children: vec![NestedVec::default()],
+                },
+            ],
+        };
+        assert_eq!(expected, decoded);
+        assert_eq!(vec![2, 0, 1, 0], decoded.serialize());
+    }
+
+    #[test]
+    fn deserialize_deep_nested_vec() {
+        let mut v = vec![1; 9_999];
+        v.push(0);
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        let decoded = de.deserialize_nested_vec(10_000).unwrap();
+        assert_eq!(10_000, decoded.depth());
+        assert_eq!(v, decoded.serialize());
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_nested_vec(9_999).is_err());
+    }
+
+    #[test]
+    fn deserialize_varuint_nonzero() {
+        let mut de = Deserializer::new(BytesMut::from(vec![0]));
+        assert!(de.deserialize_varuint_nonzero().is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![3]));
+        assert_eq!(3, de.deserialize_varuint_nonzero().unwrap().value);
+    }
+
+    #[test]
+    fn deserialize_vec_fixed_len_u32() {
+        let v = vec![0, 0, 0, 2, 0xFD, 7];
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        let decoded: Vec<u8> = de.deserialize_vec_fixed_len_u32().unwrap();
+        assert_eq!(vec![0xFD, 7], decoded);
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded: Vec<u8> = de.deserialize_vec().unwrap();
+        assert!(decoded.is_empty());
+
+        let v = vec![2, 1, 2];
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        let decoded: Vec<u8> = de.deserialize_vec().unwrap();
+        assert_eq!(vec![1, 2], decoded);
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_vec_fixed_len_u32::<u8>().is_err());
+    }
+
+    #[test]
+    fn trace_reads() {
+        let header = BlockHeader {
+            version: 1,
+            prev_block: Sha256Result::from([0; 32]),
+            merkle_root: Sha256Result::from([0; 32]),
+            timestamp: 2,
+            bits: 3,
+            nonce: 4,
+        };
+        let reads = Rc::new(RefCell::new(Vec::new()));
+        let trace_reads = reads.clone();
+        let mut de = Deserializer::new(BytesMut::from(header.serialize())).with_trace(
+            move |name, value, offset| trace_reads.borrow_mut().push((name, value, offset)),
+        );
+        BlockHeader::deserialize(&mut de).unwrap();
+        assert_eq!(
+            vec![
+                ("u32", 1, 0),
+                ("u64", 2, 68),
+                ("u32", 3, 76),
+                ("u64", 4, 80)
+            ],
+            *reads.borrow()
+        );
+    }
+
+    #[test]
+    fn deserialize_bytes_vec_max() {
+        let v = vec![3, 1, 2, 3];
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        assert_eq!(vec![1, 2, 3], de.deserialize_bytes_vec_max(3).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_bytes_vec_max(2).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn deserialize_sets() {
+        let v = vec![2, 0, 1, 0, 2];
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        let decoded: HashSet<u16> = HashSet::deserialize(&mut de).unwrap();
+        assert_eq!([1, 2].iter().cloned().collect::<HashSet<u16>>(), decoded);
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let decoded: BTreeSet<u16> = BTreeSet::deserialize(&mut de).unwrap();
+        assert_eq!(vec![1, 2], decoded.into_iter().collect::<Vec<u16>>());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Frame {
+        Ping(u64),
+        Raw(Remainder),
+    }
+
+    impl Deserialize for Frame {
+        fn deserialize(de: &mut Deserializer) -> crate::Result<Frame> {
+            match u8::deserialize(de)? {
+                0 => Ok(Frame::Ping(u64::deserialize(de)?)),
+                _ => Ok(Frame::Raw(Remainder::deserialize(de)?)),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_enum_payload() {
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 5, 6, 7]));
+        assert_eq!(
+            Frame::Raw(Remainder(vec![5, 6, 7])),
+            Frame::deserialize(&mut de).unwrap()
+        );
+        assert!(u8::deserialize(&mut de).is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0, 0, 0, 0, 0, 0, 0, 0, 9]));
+        assert_eq!(Frame::Ping(9), Frame::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn deserialize_flagged() {
+        let v = vec![0b01, 0, 7];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let flags = u8::deserialize(&mut de).unwrap();
+        let first: Flagged<u16> =
+            Flagged::deserialize_with_context(&mut de, flags & 0b01 != 0).unwrap();
+        let second: Flagged<u16> =
+            Flagged::deserialize_with_context(&mut de, flags & 0b10 != 0).unwrap();
+        assert_eq!(Flagged(Some(7)), first);
+        assert_eq!(Flagged(None), second);
+        assert_eq!(vec![0, 7], first.serialize());
+        assert!(second.serialize().is_empty());
+    }
+
+    #[test]
+    fn deserialize_btreemap() {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("aa"), 1_u8);
+        map.insert(String::from("b"), 2);
+        let raw = map.serialize();
+        assert_eq!(vec![2, 1, b'b', 2, 2, b'a', b'a', 1], raw);
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(map, BTreeMap::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn deserialize_btreemap_non_canonical() {
+        let v = vec![2, 0, 2, 7, 0, 1, 8];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(BTreeMap::<u16, u8>::deserialize(&mut de).is_err());
+
+        let v = vec![2, 0, 1, 7, 0, 1, 8];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(BTreeMap::<u16, u8>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn deserialize_header_then() {
+        let v = vec![0, 2, 0, 7, 0, 8, 0, 9];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let (count, body): (u16, Vec<u16>) = de
+            .deserialize_header_then(|count, de| {
+                (0..*count).map(|_| u16::deserialize(de)).collect()
+            })
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(vec![7_u16, 8], body);
+        assert_eq!(9, u16::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn socketaddr_routable() {
+        let loopback: SocketAddr = "127.0.0.1:4224".parse().unwrap();
+        let mut de = Deserializer::new(BytesMut::from(loopback.serialize()));
+        assert!(de.deserialize_socketaddr_routable().is_err());
+
+        let loopback: SocketAddr = "[::1]:4224".parse().unwrap();
+        let mut de = Deserializer::new(BytesMut::from(loopback.serialize()));
+        assert!(de.deserialize_socketaddr_routable().is_err());
+
+        let public: SocketAddr = "[::ffff:93.184.216.34]:4224".parse().unwrap();
+        let mut de = Deserializer::new(BytesMut::from(public.serialize()));
+        assert_eq!(
+            "93.184.216.34:4224".parse::<SocketAddr>().unwrap(),
+            de.deserialize_socketaddr_routable().unwrap()
+        );
+    }
+
+    #[test]
+    fn rle() {
+        let rle = Rle(vec![0, 0, 0, 0, 7, 0, 0, 255, 255]);
+        let v = rle.serialize();
+        assert_eq!(vec![4, 4, 0, 1, 7, 2, 0, 2, 255], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(rle, Rle::deserialize(&mut de).unwrap());
+
+        let empty = Rle(Vec::new());
+        let mut de = Deserializer::new(BytesMut::from(empty.serialize()));
+        assert_eq!(empty, Rle::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn rle_bomb() {
+        // Two runs of 2^32 zeros in 12 bytes
+        let v = vec![2, 0xFF, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0xFE];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_rle(1 << 20).is_err());
+
+        let v = vec![1, 0xFD, 0x10, 0x00, 0];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_rle(0x0FFF).is_err());
+    }
+
+    #[test]
+    fn vec_while() {
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 1, 4, 0, 42]));
+        let v: Vec<u8> = de.deserialize_vec_while(|x| *x != 0).unwrap();
+        assert_eq!(vec![3, 1, 4], v);
+        assert_eq!(42, u8::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 1]));
+        let v: Vec<u8> = de.deserialize_vec_while(|x| *x != 0).unwrap();
+        assert_eq!(vec![3, 1], v);
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0, 3, 0]));
+        assert!(de.deserialize_vec_while(|x: &u16| *x != 0).is_err());
+    }
+
+    #[test]
+    fn checkpoint_restore() {
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 2, 3]));
+        let checkpoint = de.checkpoint();
+        assert_eq!(0x0102, u16::deserialize(&mut de).unwrap());
+        de.restore(checkpoint);
+        assert_eq!(1, u8::deserialize(&mut de).unwrap());
+        assert_eq!(&[1][..], de.consumed());
+        assert_eq!(0x0203, u16::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn alt() {
+        fn tagged(de: &mut Deserializer) -> crate::Result<u32> {
+            match u8::deserialize(de)? {
+                0xAA => u32::deserialize(de),
+                tag => Err(crate::Error::Message(format!("Unknown tag {}", tag))),
+            }
+        }
+        fn short(de: &mut Deserializer) -> crate::Result<u32> {
+            u16::deserialize(de).map(u32::from)
+        }
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0x12, 0x34, 0x56]));
+        assert_eq!(0x1234, de.deserialize_alt(&[tagged, short]).unwrap());
+        assert_eq!(0x56, u8::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0x12]));
+        assert!(de.deserialize_alt(&[tagged, short]).is_err());
+        assert_eq!(0x12, u8::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn datetime() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        let datetime = Utc.with_ymd_and_hms(2019, 10, 2, 13, 37, 42).unwrap();
+        let v = datetime.serialize();
+        assert_eq!(vec![0, 0, 0, 0, 0x5D, 0x94, 0xA8, 0x26], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(datetime, DateTime::<Utc>::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(i64::MAX.to_be_bytes().to_vec()));
+        assert!(DateTime::<Utc>::deserialize(&mut de).is_err());
     }
 
     #[test]
-    fn deserialize_varuint() {
-        let mut v = Vec::new();
-        v.push(0xFD as u8);
-        v.push(42);
-        v.push(43);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
-        };
-        let decoded = de.deserialize_varuint().unwrap();
-        assert_eq!(10795, decoded.value);
+    fn bitset_flags() {
+        use crate::deserializer::UnknownBits;
+
+        let mapping = [(1, "network"), (1 << 2, "bloom"), (1 << 3, "witness")];
+        let mask: u64 = 1 | 1 << 3 | 1 << 10;
+
+        let mut de = Deserializer::new(BytesMut::from(mask.serialize()));
+        assert!(de
+            .deserialize_bitset_flags(&mapping, UnknownBits::Strict)
+            .is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(mask.serialize()));
+        assert_eq!(
+            vec!["network", "witness"],
+            de.deserialize_bitset_flags(&mapping, UnknownBits::Lenient)
+                .unwrap()
+        );
+
+        let mut de = Deserializer::new(BytesMut::from((1_u64 << 2).serialize()));
+        assert_eq!(
+            vec!["bloom"],
+            de.deserialize_bitset_flags(&mapping, UnknownBits::Strict)
+                .unwrap()
+        );
     }
 
     #[test]
-    fn deserialize_u64() {
-        let mut v = Vec::new();
-        v.push(42);
-        v.push(43);
-        v.push(44);
-        v.push(45);
-        v.push(46);
-        v.push(47);
-        v.push(48);
-        v.push(49);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
-        };
-        let decoded = de.deserialize_u64().unwrap();
-        assert_eq!(3038570946151526449, decoded);
+    fn sized() {
+        let mut de = Deserializer::new(BytesMut::from(vec![2, 0x12, 0x34, 0xFF]));
+        assert_eq!(0x1234, de.deserialize_sized::<u16>().unwrap());
+        assert_eq!(0xFF, u8::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![4, 0x12, 0x34, 0xAA, 0xBB, 0xFF]));
+        assert!(de.deserialize_sized::<u16>().is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![4, 0x12, 0x34, 0xAA, 0xBB, 0xFF]));
+        assert_eq!(0x1234, de.deserialize_sized_lenient::<u16>().unwrap());
+        assert_eq!(0xFF, u8::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 0x12, 0x34]));
+        assert!(de.deserialize_sized_lenient::<u16>().is_err());
     }
 
+    #[cfg(feature = "sha2")]
     #[test]
-    fn deserialize_u32() {
-        let mut v = Vec::new();
-        v.push(42);
-        v.push(43);
-        v.push(44);
-        v.push(45);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
+    fn trailing_checksum() {
+        use sha2::{Digest, Sha256};
+
+        let payload = vec![0x12, 0x34, 0x56];
+        let hash = Sha256::digest(&Sha256::digest(&payload));
+        let mut v = payload.clone();
+        v.extend_from_slice(&hash[..4]);
+
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        assert_eq!(0x1234, u16::deserialize(&mut de).unwrap());
+        assert_eq!(0x56, u8::deserialize(&mut de).unwrap());
+        de.verify_trailing_checksum(4).unwrap();
+
+        v[1] ^= 1;
+        let mut de = Deserializer::new(BytesMut::from(v));
+        u16::deserialize(&mut de).unwrap();
+        u8::deserialize(&mut de).unwrap();
+        assert!(de.verify_trailing_checksum(4).is_err());
+    }
+
+    #[test]
+    fn deserialize_ascii_string() {
+        let mut de = Deserializer::new(BytesMut::from(vec![4, b'p', b'i', b'n', b'g']));
+        assert_eq!("ping", de.deserialize_ascii_string().unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![3, b'a', 0xC3, 0xA9]));
+        assert!(de.deserialize_ascii_string().is_err());
+    }
+
+    #[test]
+    fn map_streaming() {
+        let v = vec![3, 1, 0, 10, 2, 0, 20, 3, 0, 30];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let mut count = 0;
+        let mut total = 0;
+        de.deserialize_map_streaming(|key: u8, value: u16| {
+            count += 1;
+            total += key as u16 * value;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(3, count);
+        assert_eq!(140, total);
+
+        let mut de = Deserializer::new(BytesMut::from(vec![200, 1, 0, 10]));
+        assert!(de
+            .deserialize_map_streaming(|_: u8, _: u16| Ok(()))
+            .is_err());
+    }
+
+    #[test]
+    fn vec_sorted() {
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 1, 4, 9]));
+        assert_eq!(vec![1, 4, 9], de.deserialize_vec_sorted::<u8>().unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 1, 9, 4]));
+        assert!(de.deserialize_vec_sorted::<u8>().is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![2, 4, 4]));
+        assert!(de.deserialize_vec_sorted::<u8>().is_err());
+    }
+
+    #[test]
+    fn max_allocations() {
+        // A list of 1000 empty lists
+        let mut v = vec![0xFD, 0x03, 0xE8];
+        v.extend_from_slice(&[0; 1000]);
+        let mut de = Deserializer::new(BytesMut::from(v.clone())).with_max_allocations(100);
+        assert!(NestedVec::deserialize(&mut de).is_err());
+
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(
+            1000,
+            NestedVec::deserialize(&mut de).unwrap().children.len()
+        );
+
+        let mut de =
+            Deserializer::new(BytesMut::from(vec![2, 1, b'a', 1, b'b'])).with_max_allocations(2);
+        assert!(Vec::<String>::deserialize(&mut de).is_err());
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn decimal() {
+        use rust_decimal::Decimal;
+        use std::str::FromStr;
+
+        let price = Decimal::from_str("-12.50").unwrap();
+        let v = Serialize::serialize(&price);
+        assert_eq!(
+            vec![0x80, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04, 0xE2],
+            v.to_vec()
+        );
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(
+            price,
+            <Decimal as Deserialize>::deserialize(&mut de).unwrap()
+        );
+
+        for value in [
+            Decimal::MAX,
+            Decimal::MIN,
+            Decimal::from_str("0.0000000000000000000000000001").unwrap(),
+            Decimal::from_str("-7.9228162514264337593543950335").unwrap(),
+        ]
+        .iter()
+        {
+            let mut de = Deserializer::new(BytesMut::from(Serialize::serialize(value)));
+            let decoded = <Decimal as Deserialize>::deserialize(&mut de).unwrap();
+            assert_eq!(value, &decoded);
+            assert_eq!(value.scale(), decoded.scale());
+        }
+
+        let mut v = vec![0, 29, 0, 0];
+        v.extend_from_slice(&[0; 12]);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(<Decimal as Deserialize>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn vec_byte_prefixed() {
+        let v = vec![6, 0, 1, 0, 2, 0, 3, 42];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(
+            vec![1, 2, 3],
+            de.deserialize_vec_byte_prefixed::<u16>().unwrap()
+        );
+        assert_eq!(42, u8::deserialize(&mut de).unwrap());
+
+        let v = vec![5, 0, 1, 0, 2, 0, 3];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(de.deserialize_vec_byte_prefixed::<u16>().is_err());
+    }
+
+    #[test]
+    fn option_hash() {
+        let hash = Some(Sha256Result::from([7; 32]));
+        let v = hash.serialize();
+        assert_eq!(33, v.len());
+        assert_eq!(1, v[0]);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(hash, Option::<Sha256Result>::deserialize(&mut de).unwrap());
+
+        let none: Option<Sha256Result> = None;
+        assert_eq!(vec![0], none.serialize().to_vec());
+        let mut de = Deserializer::new(BytesMut::from(vec![0]));
+        assert_eq!(none, Option::<Sha256Result>::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![2]));
+        assert!(Option::<Sha256Result>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn varuint_bounded() {
+        let v = vec![0xFD, 0x01, 0x00];
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        assert_eq!(256, de.deserialize_varuint_bounded(256).unwrap().value);
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        assert_eq!(256, de.deserialize_varuint_bounded(257).unwrap().value);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        match de.deserialize_varuint_bounded(255) {
+            Err(crate::Error::LengthExceeded(256, 255)) => (),
+            r => panic!("unexpected {:?}", r.map(|n| n.value)),
+        }
+
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 1, 2, 3]));
+        match de.deserialize_bytes_vec_max(2) {
+            Err(crate::Error::LengthExceeded(3, 2)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn signed_integers() {
+        assert_eq!(vec![0xFF, 0xFF, 0xFF, 0xFF], (-1_i32).serialize().to_vec());
+        let mut de = Deserializer::new(BytesMut::from(vec![0xFF, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(-1, de.deserialize_i32().unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0x80, 0xFF, 0xFE]));
+        assert_eq!(-128, de.deserialize_i8().unwrap());
+        assert_eq!(-2, de.deserialize_i16().unwrap());
+
+        for x in [i64::MIN, -1, 0, 42, i64::MAX].iter() {
+            let mut de = Deserializer::new(BytesMut::from(x.serialize()));
+            assert_eq!(*x, i64::deserialize(&mut de).unwrap());
+        }
+        assert_eq!(
+            vec![0x80, 0, 0, 0, 0, 0, 0, 0],
+            i64::MIN.serialize().to_vec()
+        );
+    }
+
+    #[test]
+    fn deserialize_bool() {
+        assert_eq!(vec![1, 0], [true.serialize(), false.serialize()].concat());
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 0, 2]));
+        assert!(bool::deserialize(&mut de).unwrap());
+        assert!(!bool::deserialize(&mut de).unwrap());
+        let error = bool::deserialize(&mut de).unwrap_err().to_string();
+        assert!(error.contains("0x02"), "{}", error);
+    }
+
+    #[test]
+    fn option_u32() {
+        let some = Some(0x0102_0304_u32);
+        let v = some.serialize();
+        assert_eq!(vec![1, 1, 2, 3, 4], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(some, Option::<u32>::deserialize(&mut de).unwrap());
+
+        let none: Option<u32> = None;
+        assert_eq!(vec![0], none.serialize().to_vec());
+        let mut de = Deserializer::new(BytesMut::from(vec![0]));
+        assert_eq!(none, Option::<u32>::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 1, 2]));
+        match Option::<u32>::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort(..)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn fixed_size_arrays() {
+        let bytes = [1u8, 2, 3, 4];
+        let v = bytes.serialize();
+        assert_eq!(vec![1, 2, 3, 4], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(bytes, <[u8; 4]>::deserialize(&mut de).unwrap());
+
+        let shorts = [0x0102_u16, 0x0304, 0x0506];
+        let v = shorts.serialize();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(shorts, <[u16; 3]>::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 2, 3, 4, 5]));
+        match <[u16; 3]>::deserialize(&mut de) {
+            Err(crate::Error::BufferTooShort(..)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let names = [String::from("a"), String::from("bc")];
+        let mut de = Deserializer::new(BytesMut::from(names.serialize()));
+        assert_eq!(names, <[String; 2]>::deserialize(&mut de).unwrap());
+        // The strings read before the failing one are dropped
+        let mut de = Deserializer::new(BytesMut::from(vec![1, b'a', 1, b'b', 2, b'c']));
+        assert!(<[String; 3]>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn padded() {
+        let v = crate::serializer::serialize_padded(b"ab", 4).unwrap();
+        assert_eq!(vec![b'a', b'b', 0, 0], v.to_vec());
+        let exact = crate::serializer::serialize_padded(b"abcd", 4).unwrap();
+        assert_eq!(b"abcd".to_vec(), exact.to_vec());
+        match crate::serializer::serialize_padded(b"abcde", 4) {
+            Err(crate::Error::LengthExceeded(5, 4)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let mut de = Deserializer::new(BytesMut::from([v, exact].concat()));
+        assert_eq!(b"ab".to_vec(), de.deserialize_padded(4).unwrap());
+        assert_eq!(b"abcd".to_vec(), de.deserialize_padded(4).unwrap());
+        assert!(de.deserialize_padded(1).is_err());
+    }
+
+    #[test]
+    fn from_slice_matches_new() {
+        let header = BlockHeader {
+            version: 1,
+            prev_block: Sha256Result::default(),
+            merkle_root: Sha256Result::default(),
+            timestamp: 2,
+            bits: 3,
+            nonce: 4,
         };
-        let decoded = de.deserialize_u32().unwrap();
-        assert_eq!(707472429, decoded);
+        let v = header.serialize();
+        let mut owned = Deserializer::new(BytesMut::from(v.to_vec()));
+        let mut borrowed = Deserializer::from_slice(&v);
+        assert_eq!(
+            BlockHeader::deserialize(&mut owned).unwrap(),
+            BlockHeader::deserialize(&mut borrowed).unwrap()
+        );
+        assert_eq!(owned.consumed(), borrowed.consumed());
     }
 
     #[test]
-    fn deserialize_u8() {
-        let mut v = Vec::new();
-        v.push(125);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
+    fn ping_pong() {
+        let ping = Ping(0x0102_0304_0506_0708);
+        let v = ping.serialize();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(ping, Ping::deserialize(&mut de).unwrap());
+
+        let pong = ping.pong();
+        let mut de = Deserializer::new(BytesMut::from(pong.serialize()));
+        let pong = Pong::deserialize(&mut de).unwrap();
+        assert!(pong.matches(&ping));
+        assert!(!pong.matches(&Ping(0)));
+    }
+
+    #[test]
+    fn remaining_and_position() {
+        let mut de = Deserializer::new(BytesMut::from(vec![0, 0, 0, 1, 2, 3]));
+        assert_eq!(6, de.remaining());
+        assert_eq!(0, de.position());
+        assert_eq!(1, u32::deserialize(&mut de).unwrap());
+        assert_eq!(2, de.remaining());
+        assert_eq!(4, de.position());
+        assert!(!de.is_empty());
+        de.extract_bytes(2).unwrap();
+        assert!(de.is_empty());
+    }
+
+    #[test]
+    fn deserialize_vec_lazy() {
+        let v = vec![1u16, 2, 3, 4, 5].serialize();
+        let mut de = Deserializer::new(BytesMut::from(v));
+        let first: Vec<u16> = de
+            .deserialize_vec_lazy::<u16>()
+            .unwrap()
+            .take(2)
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(vec![1, 2], first);
+        assert_eq!(5, de.position());
+        assert_eq!(6, de.remaining());
+    }
+
+    #[test]
+    fn invalid_string_source() {
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 0xFF]));
+        let error: Box<dyn std::error::Error> = Box::new(String::deserialize(&mut de).unwrap_err());
+        let source = error.source().unwrap();
+        assert!(source
+            .downcast_ref::<std::string::FromUtf8Error>()
+            .is_some());
+    }
+
+    #[test]
+    fn reject() {
+        let mut reject = Reject {
+            message: "tx".to_string(),
+            code: 0x10,
+            reason: "invalid".to_string(),
+            data: None,
         };
-        let decoded = de.deserialize_u8().unwrap();
-        assert_eq!(125, decoded);
+        let mut de = Deserializer::new(BytesMut::from(reject.serialize()));
+        assert_eq!(reject, Reject::deserialize(&mut de).unwrap());
+
+        reject.data = Some(Sha256Result::from([7; 32]));
+        let mut de = Deserializer::new(BytesMut::from(reject.serialize()));
+        assert_eq!(reject, Reject::deserialize(&mut de).unwrap());
+        assert!(de.is_empty());
     }
 
     #[test]
-    fn deserialize_u16() {
-        let mut v = Vec::new();
-        v.push(10);
-        v.push(15);
-        let mut de = Deserializer {
-            buffer: BytesMut::from(v),
+    fn expect_bytes() {
+        let mut de = Deserializer::new(BytesMut::from(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        de.expect_bytes(&[0xDE, 0xAD]).unwrap();
+        assert_eq!(2, de.position());
+        let error = de.expect_bytes(&[0xBE, 0xEE]).unwrap_err().to_string();
+        assert!(error.contains("unexpected trailer"), "{}", error);
+        assert!(de.expect_bytes(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn deserialize_vec_capped() {
+        let v = vec!["abc".to_string(), "defg".to_string()].serialize();
+        let mut de = Deserializer::new(BytesMut::from(v.clone()));
+        assert_eq!(
+            vec!["abc", "defg"],
+            de.deserialize_vec_capped::<String>(9).unwrap()
+        );
+        let mut de = Deserializer::new(BytesMut::from(v));
+        match de.deserialize_vec_capped::<String>(8) {
+            Err(crate::Error::LengthExceeded(9, 8)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        #[derive(Debug)]
+        struct Empty;
+        impl Deserialize for Empty {
+            fn deserialize(_: &mut Deserializer) -> crate::Result<Empty> {
+                Ok(Empty)
+            }
+        }
+        let mut de = Deserializer::new(BytesMut::from(vec![0xFF; 9])).with_max_collection_len(2);
+        match de.deserialize_vec_capped::<Empty>(usize::MAX) {
+            Err(crate::Error::LengthExceeded(u64::MAX, 2)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut de = Deserializer::new(BytesMut::from(vec![3, 0])).with_max_collection_len(2);
+        match de.deserialize_vec_capped::<u8>(10) {
+            Err(crate::Error::LengthExceeded(3, 2)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn deserialize_varuint_canonical() {
+        let cases: &[(&[u8], u64)] = &[
+            (&[0xFC], 0xFC),
+            (&[0xFD, 0, 0xFD], 0xFD),
+            (&[0xFE, 0, 1, 0, 0], 0x10000),
+            (&[0xFF, 0, 0, 0, 1, 0, 0, 0, 0], 0x100000000),
+        ];
+        for (bytes, value) in cases {
+            let mut de = Deserializer::new(BytesMut::from(bytes.to_vec()));
+            assert_eq!(*value, de.deserialize_varuint().unwrap().value);
+        }
+        let non_canonical: &[(&[u8], u64, usize)] = &[
+            (&[0xFD, 0, 0x10], 0x10, 3),
+            (&[0xFD, 0, 0xFC], 0xFC, 3),
+            (&[0xFE, 0, 0, 0xFF, 0xFF], 0xFFFF, 5),
+            (&[0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF], 0xFFFF_FFFF, 9),
+        ];
+        for (bytes, value, length) in non_canonical {
+            let mut de = Deserializer::new(BytesMut::from(bytes.to_vec()));
+            match de.deserialize_varuint() {
+                Err(crate::Error::NonCanonicalVarUint(v, l)) if v == *value && l == *length => (),
+                r => panic!("unexpected {:?}", r.map(|n| n.value)),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_enum_with_fallback() {
+        use crate::deserializer::{Fallback, Parser, RawVariant};
+
+        let parsers: &[(u64, Parser<u32>)] = &[
+            (1, |de| u32::deserialize(de)),
+            (2, |de| u8::deserialize(de).map(u32::from)),
+        ];
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 0, 0, 1, 0]));
+        assert_eq!(
+            Fallback::Known(256),
+            de.deserialize_enum_with_fallback(parsers).unwrap()
+        );
+        assert!(de.is_empty());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![7, 1, 2, 3]));
+        assert_eq!(
+            Fallback::Raw(RawVariant {
+                tag: 7,
+                bytes: vec![1, 2, 3]
+            }),
+            de.deserialize_enum_with_fallback(parsers).unwrap()
+        );
+        assert!(de.is_empty());
+    }
+
+    #[test]
+    fn capture_raw() {
+        let header = BlockHeader {
+            version: 1,
+            prev_block: Sha256Result::default(),
+            merkle_root: Sha256Result::default(),
+            timestamp: 2,
+            bits: 3,
+            nonce: 4,
         };
-        let decoded = de.deserialize_u16().unwrap();
-        assert_eq!(2575, decoded);
+        let raw = header.serialize();
+        let mut input = vec![0xAA];
+        input.extend_from_slice(&raw);
+        input.push(0xBB);
+        let mut de = Deserializer::new(BytesMut::from(input));
+        assert!(de.captured_raw().is_none());
+        u8::deserialize(&mut de).unwrap();
+        de.capture_raw(true);
+        assert_eq!(header, BlockHeader::deserialize(&mut de).unwrap());
+        assert_eq!(raw, de.captured_raw().unwrap());
+        de.capture_raw(false);
+        assert!(de.captured_raw().is_none());
+    }
+
+    #[test]
+    fn tuples() {
+        let pair = (7_u8, 0x0102_0304_u32);
+        let v = pair.serialize();
+        assert_eq!(vec![7, 1, 2, 3, 4], v.to_vec());
+        assert_eq!(5, pair.serialized_size());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(pair, <(u8, u32)>::deserialize(&mut de).unwrap());
+
+        let triple = ("ab".to_string(), vec![1_u16, 2], true);
+        let v = triple.serialize();
+        assert_eq!(vec![2, b'a', b'b', 2, 0, 1, 0, 2, 1], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(
+            triple,
+            <(String, Vec<u16>, bool)>::deserialize(&mut de).unwrap()
+        );
+
+        let mut de = Deserializer::new(BytesMut::from(vec![0, 0, 2]));
+        let error = <(String, Vec<u16>, bool)>::deserialize(&mut de)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("tuple element 2"), "{}", error);
+    }
+
+    #[cfg(feature = "std")]
+    /// Reader giving at most one byte per call and counting the bytes given
+    struct Trickle {
+        data: std::io::Cursor<Vec<u8>>,
+        given: Rc<RefCell<usize>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl std::io::Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let end = buf.len().min(1);
+            let n = self.data.read(&mut buf[..end])?;
+            *self.given.borrow_mut() += n;
+            Ok(n)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xDEADBEEFu32.serialize());
+        data.extend_from_slice(&"abc".to_string().serialize());
+        data.extend_from_slice(&vec![1u64, 2].serialize());
+        data.push(0xFF);
+        let mut de = Deserializer::from_reader(std::io::Cursor::new(data.clone()));
+        assert_eq!(0xDEADBEEF, u32::deserialize(&mut de).unwrap());
+        assert_eq!("abc", String::deserialize(&mut de).unwrap());
+        let checkpoint = de.checkpoint();
+        assert_eq!(vec![1, 2], Vec::<u64>::deserialize(&mut de).unwrap());
+        de.restore(checkpoint);
+        assert_eq!(vec![1, 2], Vec::<u64>::deserialize(&mut de).unwrap());
+        assert_eq!(&data[..data.len() - 1], de.consumed());
+        assert_eq!(
+            Remainder(vec![0xFF]),
+            Remainder::deserialize(&mut de).unwrap()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader_pulls_only_needed_bytes() {
+        let given = Rc::new(RefCell::new(0));
+        let mut data = vec![1, 2, 3, 4, 5];
+        data.extend_from_slice(&"hello".to_string().serialize());
+        let mut de = Deserializer::from_reader(Trickle {
+            data: std::io::Cursor::new(data),
+            given: given.clone(),
+        });
+        assert_eq!(0x0102, u16::deserialize(&mut de).unwrap());
+        assert_eq!(2, *given.borrow());
+        assert_eq!(3, u8::deserialize(&mut de).unwrap());
+        assert_eq!(0x0405, u16::deserialize(&mut de).unwrap());
+        assert_eq!("hello", String::deserialize(&mut de).unwrap());
+        assert_eq!(11, *given.borrow());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader_unexpected_eof() {
+        let mut de = Deserializer::from_reader(std::io::Cursor::new(vec![0xAB, 0xCD, 0xEF]));
+        match u32::deserialize(&mut de) {
+            Err(crate::Error::Io(e)) => assert_eq!(std::io::ErrorKind::UnexpectedEof, e.kind()),
+            r => panic!("expected an unexpected EOF, got {:?}", r),
+        }
+
+        let mut data = vec![0xFD, 0x01, 0x00];
+        data.extend_from_slice(&[0; 10]);
+        let mut de = Deserializer::from_reader(std::io::Cursor::new(data));
+        let error = Vec::<u64>::deserialize(&mut de).unwrap_err();
+        assert!(error.to_string().contains("IO error"), "{}", error);
+    }
+
+    #[test]
+    fn peek() {
+        let mut de = Deserializer::new(BytesMut::from(vec![0xAB, 0xCD]));
+        assert_eq!(0xAB, de.peek_u8().unwrap());
+        assert_eq!(2, de.remaining());
+        assert_eq!(&[0xAB, 0xCD][..], de.peek_bytes(2).unwrap());
+        assert_eq!(0xAB, u8::deserialize(&mut de).unwrap());
+        assert_eq!(1, de.remaining());
+        assert_eq!(1, de.position());
+        assert!(de.peek_bytes(2).is_err());
+        assert_eq!(0xCD, de.peek_u8().unwrap());
+        assert_eq!(0xCD, u8::deserialize(&mut de).unwrap());
+        match de.peek_u8() {
+            Err(crate::Error::BufferTooShort(_, 1, 0)) => (),
+            r => panic!("expected BufferTooShort, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn max_collection_len() {
+        let v = vec![1u8, 2, 3].serialize();
+        let mut de = Deserializer::new(BytesMut::from(v.clone())).with_max_collection_len(3);
+        assert_eq!(vec![1, 2, 3], Vec::<u8>::deserialize(&mut de).unwrap());
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(2);
+        match Vec::<u8>::deserialize(&mut de) {
+            Err(crate::Error::LengthExceeded(3, 2)) => (),
+            r => panic!("expected LengthExceeded, got {:?}", r),
+        }
+
+        let mut de = Deserializer::new(BytesMut::from("abc".to_string().serialize()))
+            .with_max_collection_len(2);
+        assert!(String::deserialize(&mut de).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn max_collection_len_before_reading() {
+        use std::io::Read;
+
+        // An endless stream would be read forever if the length were trusted
+        let prefix = vec![0xFF, 0, 0, 0x01, 0, 0, 0, 0, 0];
+        let stream = std::io::Cursor::new(prefix).chain(std::io::repeat(0));
+        let mut de = Deserializer::from_reader(stream);
+        match Vec::<u8>::deserialize(&mut de) {
+            Err(crate::Error::LengthExceeded(length, max)) => {
+                assert_eq!(1 << 40, length);
+                assert_eq!(Deserializer::DEFAULT_MAX_COLLECTION_LEN as u64, max);
+            }
+            r => panic!("expected LengthExceeded, got {:?}", r),
+        }
+        assert_eq!(9, de.position());
+
+        let stream = std::io::Cursor::new(vec![0xFE, 0x10, 0, 0, 0]).chain(std::io::repeat(0));
+        let mut de = Deserializer::from_reader(stream).with_max_collection_len(1000);
+        assert!(String::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn ip_addr() {
+        let v4 = Ipv4Addr::new(192, 168, 1, 42);
+        assert_eq!(vec![192, 168, 1, 42], v4.serialize().to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v4.serialize()));
+        assert_eq!(v4, Ipv4Addr::deserialize(&mut de).unwrap());
+
+        for ip in [IpAddr::V4(v4), IpAddr::V6(Ipv6Addr::LOCALHOST)].iter() {
+            let raw = ip.serialize();
+            assert_eq!(ip.serialized_size(), raw.len());
+            let mut de = Deserializer::new(BytesMut::from(raw));
+            assert_eq!(*ip, IpAddr::deserialize(&mut de).unwrap());
+            assert!(de.is_empty());
+        }
+        assert_eq!(4, IpAddr::V4(v4).serialize()[0]);
+        assert_eq!(6, IpAddr::V6(Ipv6Addr::LOCALHOST).serialize()[0]);
+
+        let mut de = Deserializer::new(BytesMut::from(vec![5, 1, 2, 3, 4]));
+        assert!(IpAddr::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn socketaddr_ipv4() {
+        let v4 = Ipv4Addr::new(192, 168, 1, 42);
+        let addr = SocketAddr::new(IpAddr::V4(v4), 4224);
+        let mut de = Deserializer::new(BytesMut::from(addr.serialize()));
+        let decoded = SocketAddr::deserialize(&mut de).unwrap();
+        assert!(decoded.is_ipv4());
+        assert_eq!(addr, decoded);
+        assert_eq!("192.168.1.42:4224", decoded.to_string());
+
+        let v6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 4224);
+        let mut de = Deserializer::new(BytesMut::from(v6.serialize()));
+        assert_eq!(v6, SocketAddr::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn deserialize_btreemap_u32_string() {
+        let map: BTreeMap<u32, String> = (0..5).map(|i| (i * 7, i.to_string())).collect();
+        let mut de = Deserializer::new(BytesMut::from(map.serialize()));
+        assert_eq!(map, BTreeMap::deserialize(&mut de).unwrap());
+        assert!(de.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hashmap_canonical() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::with_capacity(64);
+        for x in 0..32_u32 {
+            a.insert(x * 1000, x.to_string());
+            b.insert((31 - x) * 1000, (31 - x).to_string());
+        }
+        assert_eq!(a.serialize(), b.serialize());
+        let sorted: BTreeMap<u32, String> = a.clone().into_iter().collect();
+        assert_eq!(sorted.serialize(), a.serialize());
+        let mut de = Deserializer::new(BytesMut::from(a.serialize()));
+        assert_eq!(a, HashMap::deserialize(&mut de).unwrap());
+
+        let v = vec![2, 0, 2, 7, 0, 1, 8];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(HashMap::<u16, u8>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn skip() {
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 2, 3, 4, 5, 6]));
+        de.skip(4).unwrap();
+        assert_eq!(4, de.position());
+        assert_eq!(5, u8::deserialize(&mut de).unwrap());
+        match de.skip(2) {
+            Err(crate::Error::BufferTooShort(_, 2, 1)) => (),
+            r => panic!("expected BufferTooShort, got {:?}", r),
+        }
+        assert_eq!(6, u8::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(vec![0x3F, 0x80, 0, 0], 1.0_f32.serialize().to_vec());
+        for x in [
+            1.5,
+            -0.0,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::MIN_POSITIVE,
+        ]
+        .iter()
+        {
+            let mut de = Deserializer::new(BytesMut::from(x.serialize()));
+            assert_eq!(x.to_bits(), f32::deserialize(&mut de).unwrap().to_bits());
+        }
+        for x in [
+            1.5,
+            -0.0,
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+        ]
+        .iter()
+        {
+            let raw = x.serialize();
+            assert_eq!(8, raw.len());
+            let mut de = Deserializer::new(BytesMut::from(raw));
+            assert_eq!(x.to_bits(), f64::deserialize(&mut de).unwrap().to_bits());
+        }
+    }
+
+    #[test]
+    fn char() {
+        assert_eq!(vec![0, 0, 0, b'e'], 'e'.serialize().to_vec());
+        assert_eq!(vec![0, 0x01, 0xF9, 0x80], '🦀'.serialize().to_vec());
+        for c in ['e', '🦀'].iter() {
+            let mut de = Deserializer::new(BytesMut::from(c.serialize()));
+            assert_eq!(*c, char::deserialize(&mut de).unwrap());
+        }
+        for code_point in [0xD800_u32, 0x110000].iter() {
+            let mut de = Deserializer::new(BytesMut::from(code_point.serialize()));
+            let error = char::deserialize(&mut de).unwrap_err().to_string();
+            assert!(error.contains("not a Unicode scalar value"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn to_bytes_from_bytes() {
+        let value = (42_u32, "abc".to_string(), vec![1_u8, 2]);
+        let bytes = crate::to_bytes(&value);
+        assert_eq!(value.serialize().to_vec(), bytes);
+        assert_eq!(value, crate::from_bytes(bytes.clone()).unwrap());
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        match crate::from_bytes::<(u32, String, Vec<u8>)>(trailing) {
+            Err(crate::Error::TrailingBytes(1)) => (),
+            r => panic!("expected TrailingBytes, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn finish() {
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 2, 3]));
+        assert_eq!(0x0102, u16::deserialize(&mut de).unwrap());
+        assert_eq!(3, u8::deserialize(&mut de).unwrap());
+        de.finish().unwrap();
+
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 2, 3]));
+        assert_eq!(1, u8::deserialize(&mut de).unwrap());
+        match de.finish() {
+            Err(crate::Error::TrailingBytes(2)) => (),
+            r => panic!("expected TrailingBytes, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn duration() {
+        let duration = std::time::Duration::new(90, 500_000_000);
+        let v = duration.serialize();
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 0, 90, 0x1D, 0xCD, 0x65, 0],
+            v.to_vec()
+        );
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(duration, std::time::Duration::deserialize(&mut de).unwrap());
+
+        let mut v = 1_u64.serialize().to_vec();
+        v.extend_from_slice(&1_000_000_000_u32.serialize());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(std::time::Duration::deserialize(&mut de).is_err());
     }
 }
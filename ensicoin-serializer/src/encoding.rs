@@ -0,0 +1,58 @@
+use super::deserializer::Deserialize;
+use super::deserializer::Deserializer;
+use super::deserializer::Error;
+use super::deserializer::Result;
+use super::serializer::Serialize;
+use alloc::string::String;
+use bytes::BytesMut;
+
+/// Serializes `value` to URL safe base64 without padding
+pub fn serialize_base64<T: Serialize>(value: &T) -> String {
+    serialize_base64_with(value, base64::URL_SAFE_NO_PAD)
+}
+
+/// Serializes `value` to base64 with the given alphabet and padding
+pub fn serialize_base64_with<T: Serialize>(value: &T, config: base64::Config) -> String {
+    base64::encode_config(value.serialize(), config)
+}
+
+/// Deserializes a value from URL safe base64 without padding
+pub fn deserialize_base64<T: Deserialize>(encoded: &str) -> Result<T> {
+    deserialize_base64_with(encoded, base64::URL_SAFE_NO_PAD)
+}
+
+/// Deserializes a value from base64 with the given alphabet and padding
+pub fn deserialize_base64_with<T: Deserialize>(encoded: &str, config: base64::Config) -> Result<T> {
+    let bytes = match base64::decode_config(encoded, config) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(Error::Message(format!("Invalid base64: {}", e))),
+    };
+    T::deserialize(&mut Deserializer::new(BytesMut::from(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::{
+        deserialize_base64, deserialize_base64_with, serialize_base64, serialize_base64_with,
+    };
+
+    #[test]
+    fn base64_round_trip() {
+        let value = String::from("ensicoin?");
+        let encoded = serialize_base64(&value);
+        assert_eq!("CWVuc2ljb2luPw", encoded);
+        assert_eq!(value, deserialize_base64::<String>(&encoded).unwrap());
+
+        let encoded = serialize_base64_with(&value, base64::STANDARD);
+        assert_eq!("CWVuc2ljb2luPw==", encoded);
+        assert_eq!(
+            value,
+            deserialize_base64_with::<String>(&encoded, base64::STANDARD).unwrap()
+        );
+    }
+
+    #[test]
+    fn base64_invalid() {
+        assert!(deserialize_base64::<String>("CWVu*2ljb2luPw").is_err());
+    }
+}
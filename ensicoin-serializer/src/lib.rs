@@ -1,18 +1,72 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[macro_use]
+extern crate alloc;
 extern crate bytes;
 
 #[cfg(feature = "log")]
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "describe")]
+pub mod describe;
 pub mod deserializer;
+#[cfg(feature = "base64")]
+pub mod encoding;
+pub mod message;
+#[cfg(feature = "std")]
+pub mod record_log;
 pub mod serializer;
 pub mod types;
 
+pub use deserializer::from_bytes;
+pub use deserializer::Checkpoint;
 pub use deserializer::Deserialize;
+pub use deserializer::DeserializeWithContext;
 pub use deserializer::Deserializer;
 pub use deserializer::Error;
+pub use deserializer::Fallback;
+pub use deserializer::LazyVec;
+pub use deserializer::RawVariant;
 pub use deserializer::Result;
+pub use deserializer::UnknownBits;
+pub use message::Message;
+#[cfg(feature = "std")]
+pub use record_log::RecordLog;
+pub use serializer::to_bytes;
 pub use serializer::Serialize;
+#[cfg(feature = "arrayvec")]
+pub use serializer::SerializeToStack;
+pub use types::hash_from_string;
 pub use types::hash_to_string;
+pub use types::Block;
+pub use types::BlockHeader;
+pub use types::BlockLocator;
+pub use types::CompactTarget;
+pub use types::DedupHashes;
+pub use types::FeeFilter;
+pub use types::Flagged;
+pub use types::InvKind;
+pub use types::InvVect;
+pub use types::NetworkAddress;
+pub use types::Nonce;
+pub use types::OutPoint;
+pub use types::Ping;
+pub use types::Pong;
+pub use types::Reject;
+pub use types::Remainder;
+pub use types::Rle;
+pub use types::Script;
 pub use types::Sha256Result;
+pub use types::TaggedMessage;
+pub use types::TaggedStream;
+pub use types::Timestamp;
+pub use types::TlvBlock;
+pub use types::Transaction;
+pub use types::TxInput;
+pub use types::TxOutput;
+pub use types::VarInt;
+pub use types::VarInt32;
 pub use types::VarUint;
+pub use types::VersionMessage;
+pub use types::Work;
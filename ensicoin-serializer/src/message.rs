@@ -0,0 +1,52 @@
+use super::deserializer::Deserialize;
+use super::deserializer::Deserializer;
+use super::deserializer::Result;
+use super::serializer::Serialize;
+use alloc::vec::Vec;
+use bytes::BytesMut;
+use core::any::Any;
+
+/// Single entry point for types that can be both written and read, in the spirit of `prost`
+pub trait Message {
+    /// Serializes the message to a bytes vector
+    fn encode(&self) -> Vec<u8>;
+    /// Deserializes a message from a bytes vector
+    fn decode(bytes: Vec<u8>) -> Result<Self>
+    where
+        Self: Sized;
+    /// Number of bytes produced by `encode`
+    fn encoded_len(&self) -> usize;
+    /// The message as `Any`, to get back the concrete type of a boxed message
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static;
+}
+
+impl dyn Message {
+    /// The message as a `T`, if it is one
+    pub fn downcast_ref<T: Message + 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+impl<T: Serialize + Deserialize> Message for T {
+    fn encode(&self) -> Vec<u8> {
+        self.serialize().to_vec()
+    }
+
+    fn decode(bytes: Vec<u8>) -> Result<T> {
+        let mut de = Deserializer::new(BytesMut::from(bytes));
+        T::deserialize(&mut de)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.serialized_size()
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        T: 'static,
+    {
+        self
+    }
+}
@@ -0,0 +1,150 @@
+use super::deserializer::Deserialize;
+use super::deserializer::Deserializer;
+use super::deserializer::Error;
+use super::deserializer::Result;
+use super::serializer::Serialize;
+use super::types::VarUint;
+use bytes::BytesMut;
+
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+/// What to do when the last record of a log was only partially written
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TruncatedTail {
+    /// End the iteration as if the log ended before the partial record
+    Stop,
+    /// Yield an error for the partial record
+    Error,
+}
+
+/// Append only log of records, each written as a `VarUint` length followed by the record
+pub struct RecordLog;
+
+impl RecordLog {
+    /// Writes a record at the end of the log
+    pub fn append<W: Write, T: Serialize>(file: &mut W, record: &T) -> io::Result<()> {
+        let payload = record.serialize();
+        let length = VarUint {
+            value: payload.len() as u64,
+        };
+        file.write_all(&length.serialize())?;
+        file.write_all(&payload)
+    }
+
+    /// Iterates over the records of the log
+    pub fn records<R: Read, T: Deserialize>(
+        file: R,
+        truncated_tail: TruncatedTail,
+    ) -> Records<R, T> {
+        Records {
+            file,
+            truncated_tail,
+            done: false,
+            record: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the records of a log, created by `RecordLog::records`
+pub struct Records<R, T> {
+    file: R,
+    truncated_tail: TruncatedTail,
+    done: bool,
+    record: PhantomData<T>,
+}
+
+impl<R: Read, T: Deserialize> Records<R, T> {
+    fn read_payload(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut prefix = vec![0];
+        match self.file.read_exact(&mut prefix) {
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            r => r?,
+        };
+        let extra = match prefix[0] {
+            0xFD => 2,
+            0xFE => 4,
+            0xFF => 8,
+            _ => 0,
+        };
+        prefix.resize(1 + extra, 0);
+        self.file.read_exact(&mut prefix[1..])?;
+        let length = match VarUint::deserialize(&mut Deserializer::new(BytesMut::from(prefix))) {
+            Ok(n) => n.value,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        };
+        // Reading through `take` only allocates for bytes actually present in the file
+        let mut payload = Vec::new();
+        (&mut self.file).take(length).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some(payload))
+    }
+}
+
+impl<R: Read, T: Deserialize> Iterator for Records<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.done {
+            return None;
+        }
+        match self.read_payload() {
+            Ok(Some(payload)) => {
+                let mut de = Deserializer::new(BytesMut::from(payload));
+                Some(T::deserialize(&mut de))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::UnexpectedEof
+                    && self.truncated_tail == TruncatedTail::Stop =>
+            {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(Error::Message(format!(
+                    "Error in reading record: {}",
+                    e
+                ))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_log::{RecordLog, TruncatedTail};
+    use std::io::Cursor;
+
+    fn log() -> Vec<u8> {
+        let mut file = Vec::new();
+        for record in ["a", "bc", "def"].iter() {
+            RecordLog::append(&mut file, &record.to_string()).unwrap();
+        }
+        file.extend_from_slice(&[5, 4, b'g']);
+        file
+    }
+
+    #[test]
+    fn truncated_tail_stop() {
+        let records: Vec<String> = RecordLog::records(Cursor::new(log()), TruncatedTail::Stop)
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["a", "bc", "def"], records);
+    }
+
+    #[test]
+    fn truncated_tail_error() {
+        let records: Vec<crate::Result<String>> =
+            RecordLog::records(Cursor::new(log()), TruncatedTail::Error).collect();
+        assert_eq!(4, records.len());
+        assert_eq!("def", records[2].as_ref().unwrap());
+        assert!(records[3].is_err());
+    }
+}
@@ -1,121 +1,1477 @@
+use super::deserializer::Error;
+use super::deserializer::Result;
+use super::types::Block;
+use super::types::BlockHeader;
+use super::types::BlockLocator;
+use super::types::CompactTarget;
+use super::types::DedupHashes;
+use super::types::FeeFilter;
+use super::types::Flagged;
+use super::types::InvVect;
+use super::types::NestedVec;
+use super::types::NetworkAddress;
+use super::types::Nonce;
+use super::types::OutPoint;
+use super::types::Ping;
+use super::types::Pong;
+use super::types::Reject;
+use super::types::Remainder;
+use super::types::Rle;
+use super::types::Script;
 use super::types::Sha256Result;
+use super::types::Timestamp;
+use super::types::TlvBlock;
+use super::types::Transaction;
+use super::types::TxInput;
+use super::types::TxOutput;
+use super::types::VarInt;
+use super::types::VarInt32;
 use super::types::VarUint;
+use super::types::VersionMessage;
+use super::types::Work;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
 use bytes::Bytes;
-use std::net::SocketAddr;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
 
 /// Trait used to serialize a type to a bytes array
 pub trait Serialize {
     fn serialize(&self) -> Bytes;
+
+    /// Appends the serialized value to `out`, which avoids allocating a buffer per field when
+    /// serializing nested values into a single one
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.serialize());
+    }
+
+    /// Number of bytes written by `serialize`, to size a buffer or write a length before the
+    /// value. The default impl serializes the value to measure it
+    fn serialized_size(&self) -> usize {
+        self.serialize().len()
+    }
+
+    /// Writes the serialized value at the start of `buf` and returns the number of bytes
+    /// written, failing with `LengthExceeded` if `buf` is too small. `buf` is left untouched on
+    /// failure.
+    ///
+    /// The default impl goes through a temporary vector. Primitives, collections of those and
+    /// derived impls write straight into `buf` without allocating
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.serialized_size();
+        check_slice_len(size, buf)?;
+        let mut v = Vec::with_capacity(size);
+        self.serialize_into(&mut v);
+        buf[..size].copy_from_slice(&v);
+        Ok(size)
+    }
+
+    /// SHA-256 of the serialized value, stable as the serialization is canonical
+    #[cfg(feature = "sha2")]
+    fn content_hash(&self) -> Sha256Result {
+        use sha2::{Digest, Sha256};
+
+        Sha256::digest(&self.serialize())
+    }
+
+    /// Double SHA-256 of the serialized value
+    #[cfg(feature = "sha2")]
+    fn double_hash(&self) -> Sha256Result {
+        use sha2::{Digest, Sha256};
+
+        Sha256::digest(&self.content_hash())
+    }
+}
+
+/// Serializes `value` to a vector, the counterpart of `deserializer::from_bytes`
+pub fn to_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut v = Vec::with_capacity(value.serialized_size());
+    value.serialize_into(&mut v);
+    v
+}
+
+/// Fails with `LengthExceeded` if `size` bytes don't fit in `buf`, checked before writing the
+/// parts of a value so that `buf` is left untouched when it is too small
+fn check_slice_len(size: usize, buf: &[u8]) -> Result<()> {
+    if size > buf.len() {
+        return Err(Error::LengthExceeded(size as u64, buf.len() as u64));
+    }
+    Ok(())
+}
+
+/// Copies `bytes` at the start of `buf`, for `serialize_into_slice`
+fn write_slice(bytes: &[u8], buf: &mut [u8]) -> Result<usize> {
+    check_slice_len(bytes.len(), buf)?;
+    buf[..bytes.len()].copy_from_slice(bytes);
+    Ok(bytes.len())
+}
+
+/// Writes `bytes` in a fixed slot of `n` bytes, right padded with zeros. Fails if `bytes` doesn't
+/// fit in the slot
+pub fn serialize_padded(bytes: &[u8], n: usize) -> Result<Bytes> {
+    if bytes.len() > n {
+        return Err(Error::LengthExceeded(bytes.len() as u64, n as u64));
+    }
+    let mut v = bytes.to_vec();
+    v.resize(n, 0);
+    Ok(Bytes::from(v))
+}
+
+impl Serialize for u8 {
+    fn serialize(&self) -> Bytes {
+        Bytes::from(vec![*self])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn serialized_size(&self) -> usize {
+        1
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(&[*self], buf)
+    }
+}
+
+impl Serialize for u16 {
+    fn serialize(&self) -> Bytes {
+        let cp = *self;
+        Bytes::from(vec![(cp >> 8) as u8, cp as u8])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        2
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(&self.to_be_bytes(), buf)
+    }
+}
+
+impl Serialize for u32 {
+    fn serialize(&self) -> Bytes {
+        let cp = *self;
+        Bytes::from(vec![
+            (cp >> 24) as u8,
+            (cp >> 16) as u8,
+            (cp >> 8) as u8,
+            cp as u8,
+        ])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        4
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(&self.to_be_bytes(), buf)
+    }
+}
+
+impl Serialize for u64 {
+    fn serialize(&self) -> Bytes {
+        let cp = *self;
+        let mut v = Vec::new();
+        for i in 1..=8 {
+            v.push((cp >> (8 * (8 - i))) as u8);
+        }
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        8
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(&self.to_be_bytes(), buf)
+    }
+}
+
+impl Serialize for bool {
+    fn serialize(&self) -> Bytes {
+        Bytes::from(vec![*self as u8])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn serialized_size(&self) -> usize {
+        1
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u8).serialize_into_slice(buf)
+    }
+}
+
+/// Written as the two's complement bits of the value, like a `u8`
+impl Serialize for i8 {
+    fn serialize(&self) -> Bytes {
+        (*self as u8).serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        (*self as u8).serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        1
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u8).serialize_into_slice(buf)
+    }
+}
+
+/// Written as the two's complement bits of the value, like a `u16`
+impl Serialize for i16 {
+    fn serialize(&self) -> Bytes {
+        (*self as u16).serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        (*self as u16).serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        2
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u16).serialize_into_slice(buf)
+    }
+}
+
+/// Written as the two's complement bits of the value, like a `u32`
+impl Serialize for i32 {
+    fn serialize(&self) -> Bytes {
+        (*self as u32).serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        (*self as u32).serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u32).serialize_into_slice(buf)
+    }
+}
+
+/// Written as the two's complement bits of the value, like a `u64`
+impl Serialize for i64 {
+    fn serialize(&self) -> Bytes {
+        (*self as u64).serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        (*self as u64).serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        8
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u64).serialize_into_slice(buf)
+    }
+}
+
+/// Written as the bits of its IEEE 754 representation, like a `u32`
+impl Serialize for f32 {
+    fn serialize(&self) -> Bytes {
+        self.to_bits().serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.to_bits().serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        self.to_bits().serialize_into_slice(buf)
+    }
+}
+
+/// Written as the bits of its IEEE 754 representation, like a `u64`
+impl Serialize for f64 {
+    fn serialize(&self) -> Bytes {
+        self.to_bits().serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.to_bits().serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        8
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        self.to_bits().serialize_into_slice(buf)
+    }
+}
+
+/// Written as its code point, like a `u32`
+impl Serialize for char {
+    fn serialize(&self) -> Bytes {
+        (*self as u32).serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        (*self as u32).serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        (*self as u32).serialize_into_slice(buf)
+    }
+}
+
+impl Serialize for VarUint {
+    fn serialize(&self) -> Bytes {
+        match self.value {
+            0..=252 => (self.value as u8).serialize(),
+            253..=0xFFFF => {
+                let mut v = vec![0xFD_u8];
+                v.extend_from_slice(&(self.value as u16).serialize());
+                Bytes::from(v)
+            }
+            0x10000..=0xFFFFFFFF => {
+                let mut v = vec![0xFE_u8];
+                v.extend_from_slice(&(self.value as u32).serialize());
+                Bytes::from(v)
+            }
+            0x100000000..=0xFFFFFFFFFFFFFFFF => {
+                let mut v = vec![0xFF_u8];
+                v.extend_from_slice(&self.value.serialize());
+                Bytes::from(v)
+            }
+        }
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        match self.value {
+            0..=252 => out.push(self.value as u8),
+            253..=0xFFFF => {
+                out.push(0xFD);
+                (self.value as u16).serialize_into(out);
+            }
+            0x10000..=0xFFFFFFFF => {
+                out.push(0xFE);
+                (self.value as u32).serialize_into(out);
+            }
+            0x100000000..=0xFFFFFFFFFFFFFFFF => {
+                out.push(0xFF);
+                self.value.serialize_into(out);
+            }
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self.value {
+            0..=252 => 1,
+            253..=0xFFFF => 3,
+            0x10000..=0xFFFFFFFF => 5,
+            0x100000000..=0xFFFFFFFFFFFFFFFF => 9,
+        }
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        check_slice_len(self.serialized_size(), buf)?;
+        let size = match self.value {
+            0..=252 => return (self.value as u8).serialize_into_slice(buf),
+            253..=0xFFFF => {
+                buf[0] = 0xFD;
+                (self.value as u16).serialize_into_slice(&mut buf[1..])?
+            }
+            0x10000..=0xFFFFFFFF => {
+                buf[0] = 0xFE;
+                (self.value as u32).serialize_into_slice(&mut buf[1..])?
+            }
+            0x100000000..=0xFFFFFFFFFFFFFFFF => {
+                buf[0] = 0xFF;
+                self.value.serialize_into_slice(&mut buf[1..])?
+            }
+        };
+        Ok(1 + size)
+    }
+}
+
+impl Serialize for String {
+    fn serialize(&self) -> Bytes {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        let mut b = length.serialize();
+        b.extend_from_slice(self.as_bytes());
+        b
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        VarUint {
+            value: self.len() as u64,
+        }
+        .serialized_size()
+            + self.len()
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        check_slice_len(self.serialized_size(), buf)?;
+        let offset = VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into_slice(buf)?;
+        Ok(offset + write_slice(self.as_bytes(), &mut buf[offset..])?)
+    }
+}
+
+/// Written as a presence byte, 0 or 1, followed by the value if present
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize(&self) -> Bytes {
+        match self {
+            Some(x) => {
+                let mut v = Bytes::from(vec![1]);
+                v.extend_from_slice(&x.serialize());
+                v
+            }
+            None => Bytes::from(vec![0]),
+        }
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(x) => {
+                out.push(1);
+                x.serialize_into(out);
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        1 + self.as_ref().map_or(0, Serialize::serialized_size)
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        check_slice_len(self.serialized_size(), buf)?;
+        match self {
+            Some(x) => {
+                buf[0] = 1;
+                Ok(1 + x.serialize_into_slice(&mut buf[1..])?)
+            }
+            None => 0_u8.serialize_into_slice(buf),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize(&self) -> Bytes {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        let mut v = length.serialize();
+        for x in self.iter() {
+            v.extend_from_slice(&x.serialize());
+        }
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into(out);
+        for x in self.iter() {
+            x.serialize_into(out);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        length.serialized_size() + self.iter().map(Serialize::serialized_size).sum::<usize>()
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        check_slice_len(self.serialized_size(), buf)?;
+        let mut offset = VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into_slice(buf)?;
+        for x in self.iter() {
+            offset += x.serialize_into_slice(&mut buf[offset..])?;
+        }
+        Ok(offset)
+    }
+}
+
+/// Written as its `N` elements with no length prefix
+impl<T: Serialize, const N: usize> Serialize for [T; N] {
+    fn serialize(&self) -> Bytes {
+        let mut v = Bytes::new();
+        for x in self.iter() {
+            v.extend_from_slice(&x.serialize());
+        }
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        for x in self.iter() {
+            x.serialize_into(out);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.iter().map(Serialize::serialized_size).sum()
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        check_slice_len(self.serialized_size(), buf)?;
+        let mut offset = 0;
+        for x in self.iter() {
+            offset += x.serialize_into_slice(&mut buf[offset..])?;
+        }
+        Ok(offset)
+    }
+}
+
+/// Elements are written sorted by their serialized bytes so that the output doesn't depend on
+/// the hash iteration order. This costs an intermediate buffer per element and a sort; a
+/// `BTreeSet` is deterministic at no extra cost.
+#[cfg(feature = "std")]
+impl<T: Serialize, S> Serialize for HashSet<T, S> {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        self.serialize_into(&mut v);
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        let mut elements: Vec<Bytes> = self.iter().map(Serialize::serialize).collect();
+        elements.sort_unstable();
+        VarUint {
+            value: elements.len() as u64,
+        }
+        .serialize_into(out);
+        for x in elements.iter() {
+            out.extend_from_slice(x);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        length.serialized_size() + self.iter().map(Serialize::serialized_size).sum::<usize>()
+    }
+}
+
+impl<T: Serialize> Serialize for BTreeSet<T> {
+    fn serialize(&self) -> Bytes {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        let mut v = length.serialize();
+        for x in self.iter() {
+            v.extend_from_slice(&x.serialize());
+        }
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into(out);
+        for x in self.iter() {
+            x.serialize_into(out);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        let length = VarUint {
+            value: self.len() as u64,
+        };
+        length.serialized_size() + self.iter().map(Serialize::serialized_size).sum::<usize>()
+    }
+}
+
+/// Writes the entries of a map sorted by the serialized bytes of their keys, the canonical order
+/// checked when deserializing
+fn serialize_map<'a, K, V, I>(map: I, out: &mut Vec<u8>)
+where
+    K: Serialize + 'a,
+    V: Serialize + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+{
+    let mut entries: Vec<(Bytes, &V)> = map.map(|(k, v)| (k.serialize(), v)).collect();
+    entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    VarUint {
+        value: entries.len() as u64,
+    }
+    .serialize_into(out);
+    for (key, value) in entries.iter() {
+        out.extend_from_slice(key);
+        value.serialize_into(out);
+    }
+}
+
+/// Number of bytes written by `serialize_map` for a map of `len` entries
+fn map_serialized_size<'a, K, V, I>(len: usize, map: I) -> usize
+where
+    K: Serialize + 'a,
+    V: Serialize + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+{
+    let length = VarUint { value: len as u64 };
+    length.serialized_size()
+        + map
+            .map(|(k, v)| k.serialized_size() + v.serialized_size())
+            .sum::<usize>()
+}
+
+/// Entries are written sorted by the serialized bytes of their keys. For keys such as integers
+/// it is also the order of the map.
+impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        serialize_map(self.iter(), &mut v);
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        serialize_map(self.iter(), out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        map_serialized_size(self.len(), self.iter())
+    }
+}
+
+/// Entries are written sorted by the serialized bytes of their keys, so that the output doesn't
+/// depend on the hash iteration order and is the same as for a `BTreeMap` of the same entries
+#[cfg(feature = "std")]
+impl<K: Serialize, V: Serialize, S> Serialize for HashMap<K, V, S> {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        serialize_map(self.iter(), &mut v);
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        serialize_map(self.iter(), out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        map_serialized_size(self.len(), self.iter())
+    }
+}
+
+/// Tuples are written as their elements in order, with no length prefix
+macro_rules! impl_serialize_tuple {
+    ($($t:ident: $i:tt),+) => {
+        impl<$($t: Serialize),+> Serialize for ($($t,)+) {
+            fn serialize(&self) -> Bytes {
+                let mut v = Vec::new();
+                self.serialize_into(&mut v);
+                Bytes::from(v)
+            }
+
+            fn serialize_into(&self, out: &mut Vec<u8>) {
+                $(self.$i.serialize_into(out);)+
+            }
+
+            fn serialized_size(&self) -> usize {
+                0 $(+ self.$i.serialized_size())+
+            }
+
+            fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+                check_slice_len(self.serialized_size(), buf)?;
+                let mut offset = 0;
+                $(offset += self.$i.serialize_into_slice(&mut buf[offset..])?;)+
+                Ok(offset)
+            }
+        }
+    };
+}
+
+impl_serialize_tuple!(A: 0);
+impl_serialize_tuple!(A: 0, B: 1);
+impl_serialize_tuple!(A: 0, B: 1, C: 2);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+impl_serialize_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+
+impl Serialize for Sha256Result {
+    fn serialize(&self) -> Bytes {
+        Bytes::from(self.to_vec())
+    }
+
+    fn serialized_size(&self) -> usize {
+        32
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(self, buf)
+    }
+}
+
+impl Serialize for Ipv4Addr {
+    fn serialize(&self) -> Bytes {
+        Bytes::from(&self.octets()[..])
+    }
+
+    fn serialized_size(&self) -> usize {
+        4
+    }
+
+    fn serialize_into_slice(&self, buf: &mut [u8]) -> Result<usize> {
+        write_slice(&self.octets(), buf)
+    }
+}
+
+/// Written as a tag of 4 or 6 followed by the 4 or 16 bytes of the address
+impl Serialize for IpAddr {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        match self {
+            IpAddr::V4(ip) => {
+                v.push(4);
+                v.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                v.push(6);
+                v.extend_from_slice(&ip.octets());
+            }
+        };
+        Bytes::from(v)
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self {
+            IpAddr::V4(_) => 5,
+            IpAddr::V6(_) => 17,
+        }
+    }
+}
+
+/// Written as 16 bytes of IPv6 address then the port, IPv4 addresses being mapped to
+/// `::ffff:a.b.c.d`. Mapped addresses are read back as IPv4 addresses
+impl Serialize for SocketAddr {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        match self {
+            SocketAddr::V4(addr) => v.extend_from_slice(&addr.ip().to_ipv6_mapped().octets()),
+            SocketAddr::V6(addr) => v.extend_from_slice(&addr.ip().octets()),
+        };
+        v.extend_from_slice(&self.port().serialize());
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        match self {
+            SocketAddr::V4(addr) => out.extend_from_slice(&addr.ip().to_ipv6_mapped().octets()),
+            SocketAddr::V6(addr) => out.extend_from_slice(&addr.ip().octets()),
+        };
+        self.port().serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        18
+    }
+}
+
+impl Serialize for OutPoint {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.tx_hash.serialize();
+        v.extend_from_slice(&self.index.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.tx_hash.serialize_into(out);
+        self.index.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.tx_hash.serialized_size() + self.index.serialized_size()
+    }
+}
+
+impl Serialize for InvVect {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.kind.serialize();
+        v.extend_from_slice(&self.hash.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.kind.serialize_into(out);
+        self.hash.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.kind.serialized_size() + self.hash.serialized_size()
+    }
+}
+
+impl Serialize for NestedVec {
+    fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            let length = VarUint {
+                value: node.children.len() as u64,
+            };
+            v.extend_from_slice(&length.serialize());
+            stack.extend(node.children.iter().rev());
+        }
+        Bytes::from(v)
+    }
+}
+
+impl Serialize for BlockHeader {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.version.serialize();
+        v.extend_from_slice(&self.prev_block.serialize());
+        v.extend_from_slice(&self.merkle_root.serialize());
+        v.extend_from_slice(&self.timestamp.serialize());
+        v.extend_from_slice(&self.bits.serialize());
+        v.extend_from_slice(&self.nonce.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.version.serialize_into(out);
+        self.prev_block.serialize_into(out);
+        self.merkle_root.serialize_into(out);
+        self.timestamp.serialize_into(out);
+        self.bits.serialize_into(out);
+        self.nonce.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.version.serialized_size()
+            + self.prev_block.serialized_size()
+            + self.merkle_root.serialized_size()
+            + self.timestamp.serialized_size()
+            + self.bits.serialized_size()
+            + self.nonce.serialized_size()
+    }
+}
+
+impl Serialize for Script {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.0.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.serialized_size()
+    }
+}
+
+impl Serialize for NetworkAddress {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.timestamp.serialize();
+        v.extend_from_slice(&self.services.serialize());
+        v.extend_from_slice(&self.addr.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.timestamp.serialize_into(out);
+        self.services.serialize_into(out);
+        self.addr.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.timestamp.serialized_size()
+            + self.services.serialized_size()
+            + self.addr.serialized_size()
+    }
+}
+
+impl Serialize for CompactTarget {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.0.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.serialized_size()
+    }
 }
 
-impl Serialize for u8 {
+impl Serialize for Remainder {
     fn serialize(&self) -> Bytes {
-        Bytes::from(vec![self.clone()])
+        Bytes::from(self.0.clone())
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.len()
     }
 }
 
-impl Serialize for u16 {
+impl<T: Serialize> Serialize for Flagged<T> {
     fn serialize(&self) -> Bytes {
-        let cp = self.clone();
-        let mut v = Vec::new();
-        v.push((cp >> 8) as u8);
-        v.push(cp as u8);
-        Bytes::from(v)
+        match &self.0 {
+            Some(x) => x.serialize(),
+            None => Bytes::new(),
+        }
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        if let Some(x) = &self.0 {
+            x.serialize_into(out);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.as_ref().map_or(0, Serialize::serialized_size)
     }
 }
 
-impl Serialize for u32 {
+impl Serialize for TxInput {
     fn serialize(&self) -> Bytes {
-        let cp = self.clone();
-        let mut v = Vec::new();
-        v.push((cp >> 24) as u8);
-        v.push((cp >> 16) as u8);
-        v.push((cp >> 8) as u8);
-        v.push(cp as u8);
-        Bytes::from(v)
+        let mut v = self.previous_output.serialize();
+        v.extend_from_slice(&self.script.serialize());
+        v.extend_from_slice(&self.sequence.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.previous_output.serialize_into(out);
+        self.script.serialize_into(out);
+        self.sequence.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.previous_output.serialized_size()
+            + self.script.serialized_size()
+            + self.sequence.serialized_size()
     }
 }
 
-impl Serialize for u64 {
+impl Serialize for TxOutput {
     fn serialize(&self) -> Bytes {
-        let cp = self.clone();
-        let mut v = Vec::new();
-        for i in 1..=8 {
-            v.push((cp >> (8 * (8 - i))) as u8);
-        }
-        Bytes::from(v)
+        let mut v = self.value.serialize();
+        v.extend_from_slice(&self.script.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.value.serialize_into(out);
+        self.script.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.value.serialized_size() + self.script.serialized_size()
     }
 }
 
-impl Serialize for VarUint {
+impl Serialize for Transaction {
     fn serialize(&self) -> Bytes {
-        match self.value {
-            0..=252 => (self.value as u8).serialize(),
-            253..=0xFFFF => {
-                let mut v = vec![0xFD as u8];
-                v.extend_from_slice(&(self.value as u16).serialize());
-                Bytes::from(v)
-            }
-            0x10000..=0xFFFFFFFF => {
-                let mut v = vec![0xFE as u8];
-                v.extend_from_slice(&(self.value as u32).serialize());
-                Bytes::from(v)
-            }
-            0x100000000..=0xFFFFFFFFFFFFFFFF => {
-                let mut v = vec![0xFF as u8];
-                v.extend_from_slice(&(self.value as u64).serialize());
-                Bytes::from(v)
+        let mut v = self.version.serialize();
+        v.extend_from_slice(&self.flags.serialize());
+        v.extend_from_slice(&self.inputs.serialize());
+        v.extend_from_slice(&self.outputs.serialize());
+        v.extend_from_slice(&self.locktime.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.version.serialize_into(out);
+        self.flags.serialize_into(out);
+        self.inputs.serialize_into(out);
+        self.outputs.serialize_into(out);
+        self.locktime.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.version.serialized_size()
+            + self.flags.serialized_size()
+            + self.inputs.serialized_size()
+            + self.outputs.serialized_size()
+            + self.locktime.serialized_size()
+    }
+}
+
+/// Serialization to a fixed capacity buffer on the stack, for types whose serialized size is
+/// at most `N` bytes, avoiding the allocation made by `serialize`
+#[cfg(feature = "arrayvec")]
+pub trait SerializeToStack<const N: usize> {
+    fn serialize_to_stack(&self) -> arrayvec::ArrayVec<u8, N>;
+}
+
+#[cfg(feature = "arrayvec")]
+macro_rules! impl_serialize_to_stack {
+    ($($t:ty => $n:expr),*) => {
+        $(
+            impl SerializeToStack<$n> for $t {
+                fn serialize_to_stack(&self) -> arrayvec::ArrayVec<u8, $n> {
+                    arrayvec::ArrayVec::from(self.to_be_bytes())
+                }
             }
+        )*
+    };
+}
+
+#[cfg(feature = "arrayvec")]
+impl_serialize_to_stack!(u8 => 1, u16 => 2, u32 => 4, u64 => 8);
+
+#[cfg(feature = "arrayvec")]
+impl SerializeToStack<9> for VarUint {
+    fn serialize_to_stack(&self) -> arrayvec::ArrayVec<u8, 9> {
+        let mut v = arrayvec::ArrayVec::new();
+        let (prefix, bytes) = match self.value {
+            0..=252 => (None, 1),
+            253..=0xFFFF => (Some(0xFD), 2),
+            0x10000..=0xFFFFFFFF => (Some(0xFE), 4),
+            _ => (Some(0xFF), 8),
+        };
+        if let Some(prefix) = prefix {
+            v.push(prefix);
+        }
+        v.try_extend_from_slice(&self.value.to_be_bytes()[8 - bytes..])
+            .unwrap();
+        v
+    }
+}
+
+#[cfg(feature = "arrayvec")]
+impl SerializeToStack<32> for Sha256Result {
+    fn serialize_to_stack(&self) -> arrayvec::ArrayVec<u8, 32> {
+        let mut v = arrayvec::ArrayVec::new();
+        v.try_extend_from_slice(self).unwrap();
+        v
+    }
+}
+
+/// Runs of equal bytes of `rle`, as their length and byte
+fn rle_runs(rle: &Rle) -> Vec<(u64, u8)> {
+    let mut runs: Vec<(u64, u8)> = Vec::new();
+    for byte in rle.0.iter() {
+        match runs.last_mut() {
+            Some((count, last)) if last == byte => *count += 1,
+            _ => runs.push((1, *byte)),
         }
     }
+    runs
 }
 
-impl Serialize for String {
+impl Serialize for Rle {
     fn serialize(&self) -> Bytes {
+        let mut v = Vec::new();
+        self.serialize_into(&mut v);
+        Bytes::from(v)
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        let runs = rle_runs(self);
+        VarUint {
+            value: runs.len() as u64,
+        }
+        .serialize_into(out);
+        for (count, byte) in runs {
+            VarUint { value: count }.serialize_into(out);
+            out.push(byte);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        let runs = rle_runs(self);
         let length = VarUint {
-            value: self.len() as u64,
+            value: runs.len() as u64,
         };
-        let mut b = length.serialize();
-        b.extend_from_slice(self.as_bytes());
-        b
+        length.serialized_size()
+            + runs
+                .iter()
+                .map(|(count, _)| VarUint { value: *count }.serialized_size() + 1)
+                .sum::<usize>()
     }
 }
 
-impl<T: Serialize> Serialize for Vec<T> {
+impl Serialize for Nonce {
+    fn serialize(&self) -> Bytes {
+        Bytes::from(&self.0[..])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+
+    fn serialized_size(&self) -> usize {
+        8
+    }
+}
+
+/// Encoded as the number of seconds since the Unix epoch in a big endian `i64`, sub-second
+/// precision being dropped
+#[cfg(feature = "chrono")]
+impl Serialize for chrono::DateTime<chrono::Utc> {
+    fn serialize(&self) -> Bytes {
+        self.timestamp().serialize()
+    }
+}
+
+impl Serialize for TlvBlock {
     fn serialize(&self) -> Bytes {
+        let mut v = VarUint {
+            value: self.len() as u64,
+        }
+        .serialize();
+        for (tag, value) in self.iter() {
+            v.extend_from_slice(&tag.serialize());
+            v.extend_from_slice(&value.serialize());
+        }
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        VarUint {
+            value: self.len() as u64,
+        }
+        .serialize_into(out);
+        for (tag, value) in self.iter() {
+            tag.serialize_into(out);
+            value.serialize_into(out);
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
         let length = VarUint {
             value: self.len() as u64,
         };
-        let mut v = length.serialize();
-        for x in self.iter() {
-            v.extend_from_slice(&x.serialize());
+        length.serialized_size()
+            + self
+                .iter()
+                .map(|(tag, value)| tag.serialized_size() + value.serialized_size())
+                .sum::<usize>()
+    }
+}
+
+impl Serialize for VarInt {
+    fn serialize(&self) -> Bytes {
+        VarUint {
+            value: self.zigzag(),
+        }
+        .serialize()
+    }
+}
+
+impl Serialize for VarInt32 {
+    fn serialize(&self) -> Bytes {
+        VarUint {
+            value: self.zigzag() as u64,
         }
+        .serialize()
+    }
+}
+
+impl Serialize for BlockLocator {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.hashes.serialize();
+        v.extend_from_slice(&self.stop.serialize());
         v
     }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.hashes.serialize_into(out);
+        self.stop.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.hashes.serialized_size() + self.stop.serialized_size()
+    }
 }
 
-impl Serialize for Sha256Result {
+impl Serialize for Work {
     fn serialize(&self) -> Bytes {
-        Bytes::from(self.to_vec())
+        Bytes::from(&self.0.to_be_bytes()[..])
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0.to_be_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        16
     }
 }
 
-impl Serialize for SocketAddr {
+/// Encoded as its flags then the high, middle and low words of its 96 bits mantissa, as big
+/// endian `u32`s. The flags hold the scale in bits 16 to 23 and the sign in bit 31. `Decimal`
+/// has inherent `serialize` and `deserialize` methods, so the traits must be called explicitly
+#[cfg(feature = "rust_decimal")]
+impl Serialize for rust_decimal::Decimal {
+    fn serialize(&self) -> Bytes {
+        let mantissa = self.mantissa().unsigned_abs();
+        let flags = (self.scale() << 16) | ((self.is_sign_negative() as u32) << 31);
+        let mut v = flags.serialize();
+        v.extend_from_slice(&((mantissa >> 64) as u32).serialize());
+        v.extend_from_slice(&((mantissa >> 32) as u32).serialize());
+        v.extend_from_slice(&(mantissa as u32).serialize());
+        v
+    }
+}
+
+/// Calls `f` with each hash of `hashes`, and with the index of its first occurrence among the
+/// distinct hashes if it is repeated
+fn dedup_entries<'a>(hashes: &'a DedupHashes, mut f: impl FnMut(Option<u64>, &'a Sha256Result)) {
+    let mut table = BTreeMap::new();
+    for hash in hashes.0.iter() {
+        match table.get(hash) {
+            Some(index) => f(Some(*index), hash),
+            None => {
+                table.insert(hash, table.len() as u64);
+                f(None, hash);
+            }
+        }
+    }
+}
+
+impl Serialize for DedupHashes {
     fn serialize(&self) -> Bytes {
         let mut v = Vec::new();
-        match self {
-            SocketAddr::V4(addr) => v.extend_from_slice(&addr.ip().to_ipv6_mapped().octets()),
-            SocketAddr::V6(addr) => v.extend_from_slice(&addr.ip().octets()),
-        };
-        v.extend_from_slice(&self.port().serialize());
+        self.serialize_into(&mut v);
         Bytes::from(v)
     }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        VarUint {
+            value: self.0.len() as u64,
+        }
+        .serialize_into(out);
+        dedup_entries(self, |index, hash| match index {
+            Some(index) => VarUint { value: index + 1 }.serialize_into(out),
+            None => {
+                out.push(0);
+                out.extend_from_slice(hash);
+            }
+        });
+    }
+
+    fn serialized_size(&self) -> usize {
+        let mut size = VarUint {
+            value: self.0.len() as u64,
+        }
+        .serialized_size();
+        dedup_entries(self, |index, _| {
+            size += match index {
+                Some(index) => VarUint { value: index + 1 }.serialized_size(),
+                None => 33,
+            }
+        });
+        size
+    }
+}
+
+impl Serialize for FeeFilter {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.0.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.serialized_size()
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialized_size(&self) -> usize {
+        8
+    }
+}
+
+/// Written as its whole seconds as a `u64` then its nanoseconds as a `u32`
+impl Serialize for Duration {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.as_secs().serialize();
+        v.extend_from_slice(&self.subsec_nanos().serialize());
+        v
+    }
+
+    fn serialized_size(&self) -> usize {
+        12
+    }
+}
+
+impl Serialize for Ping {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.0.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.serialized_size()
+    }
+}
+
+impl Serialize for Pong {
+    fn serialize(&self) -> Bytes {
+        self.0.serialize()
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.0.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.serialized_size()
+    }
+}
+
+impl Serialize for Reject {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.message.serialize();
+        v.extend_from_slice(&self.code.serialize());
+        v.extend_from_slice(&self.reason.serialize());
+        v.extend_from_slice(&self.data.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.message.serialize_into(out);
+        self.code.serialize_into(out);
+        self.reason.serialize_into(out);
+        self.data.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.message.serialized_size()
+            + self.code.serialized_size()
+            + self.reason.serialized_size()
+            + self.data.serialized_size()
+    }
+}
+
+impl Serialize for VersionMessage {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.version.serialize();
+        v.extend_from_slice(&self.services.serialize());
+        v.extend_from_slice(&self.timestamp.serialize());
+        v.extend_from_slice(&self.addr_recv.serialize());
+        v.extend_from_slice(&self.addr_from.serialize());
+        v.extend_from_slice(&self.nonce.serialize());
+        v.extend_from_slice(&self.user_agent.serialize());
+        v.extend_from_slice(&self.start_height.serialize());
+        v.extend_from_slice(&self.relay.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.version.serialize_into(out);
+        self.services.serialize_into(out);
+        self.timestamp.serialize_into(out);
+        self.addr_recv.serialize_into(out);
+        self.addr_from.serialize_into(out);
+        self.nonce.serialize_into(out);
+        self.user_agent.serialize_into(out);
+        self.start_height.serialize_into(out);
+        self.relay.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.version.serialized_size()
+            + self.services.serialized_size()
+            + self.timestamp.serialized_size()
+            + self.addr_recv.serialized_size()
+            + self.addr_from.serialized_size()
+            + self.nonce.serialized_size()
+            + self.user_agent.serialized_size()
+            + self.start_height.serialized_size()
+            + self.relay.serialized_size()
+    }
+}
+
+impl Serialize for Block {
+    fn serialize(&self) -> Bytes {
+        let mut v = self.header.serialize();
+        v.extend_from_slice(&self.transactions.serialize());
+        v
+    }
+
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        self.header.serialize_into(out);
+        self.transactions.serialize_into(out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.header.serialized_size() + self.transactions.serialized_size()
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_cast, clippy::vec_init_then_push)]
 mod tests {
     use crate::serializer::Serialize;
     use crate::types::VarUint;
+    #[cfg(feature = "std")]
+    use std::collections::{BTreeSet, HashSet};
 
     #[test]
     fn serialize_vec() {
@@ -154,4 +1510,224 @@ mod tests {
     fn serialize_uint16() {
         assert_eq!(vec![50, 122], (12922 as u16).serialize());
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serialize_hashset_canonical() {
+        let mut a = HashSet::new();
+        let mut b = HashSet::with_capacity(64);
+        for x in 0..32_u32 {
+            a.insert(x * 1000);
+            b.insert((31 - x) * 1000);
+        }
+        assert_eq!(a.serialize(), b.serialize());
+        let sorted: BTreeSet<u32> = a.iter().cloned().collect();
+        assert_eq!(sorted.serialize(), a.serialize());
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn serialize_to_stack() {
+        use crate::serializer::SerializeToStack;
+        use crate::Sha256Result;
+
+        assert_eq!(&152_u8.serialize()[..], &152_u8.serialize_to_stack()[..]);
+        assert_eq!(
+            &12922_u16.serialize()[..],
+            &12922_u16.serialize_to_stack()[..]
+        );
+        assert_eq!(
+            &756980522_u32.serialize()[..],
+            &756980522_u32.serialize_to_stack()[..]
+        );
+        let x: u64 = 11420619222089223280;
+        assert_eq!(&x.serialize()[..], &x.serialize_to_stack()[..]);
+        for value in [42, 253, 0xFFFF, 0x10000, 0x100000000].iter() {
+            let var_uint = VarUint { value: *value };
+            assert_eq!(
+                &var_uint.serialize()[..],
+                &var_uint.serialize_to_stack()[..]
+            );
+        }
+        let hash = Sha256Result::from([3; 32]);
+        assert_eq!(&hash.serialize()[..], &hash.serialize_to_stack()[..]);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn content_hash() {
+        use crate::hash_to_string;
+        use crate::types::Remainder;
+
+        let abc = Remainder(b"abc".to_vec());
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hash_to_string(&abc.content_hash())
+        );
+        assert_eq!(
+            "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+            hash_to_string(&abc.double_hash())
+        );
+    }
+
+    /// Calls `check` on a value of each composite type overriding `serialize_into` and
+    /// `serialized_size`
+    macro_rules! check_composites {
+        ($check:ident) => {{
+            use crate::types::*;
+            use std::collections::{BTreeMap, BTreeSet};
+            use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+            let hash = |b: u8| crate::Sha256Result::from([b; 32]);
+            let v4 = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 4224);
+            let v6 = SocketAddr::new(Ipv6Addr::new(1, 2, 3, 4, 5, 6, 7, 8).into(), 80);
+            $check([1_u16, 2, 3]);
+            $check([1_u8, 2, 3].iter().cloned().collect::<BTreeSet<u8>>());
+            $check(
+                [(300_u16, "a".to_string()), (2, "b".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect::<BTreeMap<_, _>>(),
+            );
+            #[cfg(feature = "std")]
+            {
+                use std::collections::{HashMap, HashSet};
+                $check([1_u16, 300, 2].iter().cloned().collect::<HashSet<u16>>());
+                $check(
+                    [(300_u16, 1_u8), (2, 2), (7, 3)]
+                        .iter()
+                        .cloned()
+                        .collect::<HashMap<_, _>>(),
+                );
+            }
+            $check(v4);
+            $check(v6);
+            $check(NetworkAddress {
+                timestamp: 1,
+                services: 2,
+                addr: v6,
+            });
+            $check(InvVect::new(InvKind::Block, hash(1)));
+            $check(CompactTarget(0x1d00_ffff));
+            $check(Reject {
+                message: "tx".to_string(),
+                code: 0x10,
+                reason: "bad".to_string(),
+                data: Some(hash(2)),
+            });
+            $check(VersionMessage {
+                version: 1,
+                services: 2,
+                timestamp: 3,
+                addr_recv: v4,
+                addr_from: v6,
+                nonce: Nonce([4; 8]),
+                user_agent: "ensicoin".to_string(),
+                start_height: 5,
+                relay: true,
+            });
+            $check(BlockLocator {
+                hashes: vec![hash(1), hash(2)],
+                stop: hash(3),
+            });
+            let mut tlv = TlvBlock::new();
+            tlv.insert(2, vec![1; 300]);
+            tlv.insert(1, Vec::new());
+            $check(tlv);
+            let mut rle = vec![7; 300];
+            rle.extend_from_slice(&[1, 2, 2]);
+            $check(Rle(rle));
+            $check(DedupHashes(vec![hash(1), hash(2), hash(1), hash(1)]));
+            $check(Flagged(Some(5_u32)));
+            $check(Flagged(None::<u32>));
+            $check(Nonce([1; 8]));
+            $check(Remainder(vec![1, 2]));
+            $check(Work(5));
+            $check(FeeFilter(1000));
+            $check(Ping(1));
+            $check(Pong(2));
+        }};
+    }
+
+    #[test]
+    fn serialize_into_matches_serialize() {
+        fn check<T: Serialize>(x: T) {
+            let mut v = Vec::new();
+            x.serialize_into(&mut v);
+            assert_eq!(x.serialize().to_vec(), v);
+        }
+        check(0xAB_u8);
+        check(0xABCD_u16);
+        check(0xABCD_EF01_u32);
+        check(0x0123_4567_89AB_CDEF_u64);
+        check(-5_i8);
+        check(-5_i16);
+        check(-5_i32);
+        check(-5_i64);
+        check(true);
+        for value in [0xFC, 0xFD, 0xFFFF, 0x10000, 0x1_0000_0000].iter() {
+            check(VarUint { value: *value });
+        }
+        check("abc".to_string());
+        check(Some(7_u32));
+        check(None::<u32>);
+        check(vec![1_u16, 2, 3]);
+        check_composites!(check);
+    }
+
+    #[test]
+    fn serialized_size_matches_serialize() {
+        fn check<T: Serialize>(x: T) {
+            assert_eq!(x.serialize().len(), x.serialized_size());
+        }
+        check(0xAB_u8);
+        check(0xABCD_u16);
+        check(0xABCD_EF01_u32);
+        check(0x0123_4567_89AB_CDEF_u64);
+        check(-5_i64);
+        check(false);
+        for value in [0xFC, 0xFD, 0xFFFF, 0x10000, 0xFFFF_FFFF, 0x1_0000_0000].iter() {
+            check(VarUint { value: *value });
+        }
+        check("a".repeat(300));
+        check(Some(7_u32));
+        check(None::<u32>);
+        check(vec!["ab".to_string(); 3]);
+        check([1_u16, 2, 3]);
+        check(crate::Sha256Result::default());
+        check_composites!(check);
+    }
+
+    #[test]
+    fn serialize_into_slice_matches_serialize() {
+        fn check<T: Serialize>(x: T) {
+            let expected = x.serialize().to_vec();
+            let mut buf = [0xAA; 400];
+            assert_eq!(expected.len(), x.serialize_into_slice(&mut buf).unwrap());
+            assert_eq!(expected, buf[..expected.len()].to_vec());
+            assert_eq!(0xAA, buf[expected.len()]);
+            let mut small = vec![0xAA; expected.len() - 1];
+            assert!(x.serialize_into_slice(&mut small).is_err());
+            assert_eq!(vec![0xAA; expected.len() - 1], small);
+        }
+        check(0xAB_u8);
+        check(0xABCD_u16);
+        check(0xABCD_EF01_u32);
+        check(0x0123_4567_89AB_CDEF_u64);
+        check(-5_i32);
+        check(true);
+        check(1.5_f64);
+        check('é');
+        for value in [0xFC, 0xFD, 0xFFFF, 0x10000, 0x1_0000_0000].iter() {
+            check(VarUint { value: *value });
+        }
+        check("a".repeat(300));
+        check(Some(7_u32));
+        check(None::<u32>);
+        check(vec!["ab".to_string(); 3]);
+        check([1_u16, 2, 3]);
+        check((1_u8, "b".to_string()));
+        check(crate::Sha256Result::default());
+        check(core::net::Ipv4Addr::new(1, 2, 3, 4));
+    }
 }
@@ -1,21 +1,934 @@
+use crate::deserializer::Error;
+use crate::message::Message;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::net::SocketAddr;
+
 /// Type representing a Unit of variable length as described in the [ensicoin
 /// protocol](https://github.com/EnsicoinDevs/ensicoin/blob/master/messages.md#variable-length-integer-var_uint)
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct VarUint {
     pub value: u64,
 }
 
+impl VarUint {
+    /// Decodes a `VarUint` at the start of `bytes`, returning it with the number of bytes it
+    /// takes. Values not written in their shortest form are rejected
+    pub fn from_slice(bytes: &[u8]) -> Result<(VarUint, usize), Error> {
+        let (length, min) = match bytes.first() {
+            None => return Err(Error::BufferTooShort("VarUint", 1, 0)),
+            Some(0xFD) => (3, 0xFD),
+            Some(0xFE) => (5, 0x10000),
+            Some(0xFF) => (9, 0x100000000),
+            Some(b) => return Ok((VarUint { value: *b as u64 }, 1)),
+        };
+        if bytes.len() < length {
+            return Err(Error::BufferTooShort("VarUint", length, bytes.len()));
+        }
+        let value = bytes[1..length]
+            .iter()
+            .fold(0, |value, b| (value << 8) | *b as u64);
+        if value < min {
+            return Err(Error::NonCanonicalVarUint(value, length));
+        }
+        Ok((VarUint { value }, length))
+    }
+
+    /// Sum of the value and `rhs`, or `None` on overflow
+    pub fn checked_add(self, rhs: VarUint) -> Option<VarUint> {
+        self.value
+            .checked_add(rhs.value)
+            .map(|value| VarUint { value })
+    }
+
+    /// Difference of the value and `rhs`, or `None` if `rhs` is greater
+    pub fn checked_sub(self, rhs: VarUint) -> Option<VarUint> {
+        self.value
+            .checked_sub(rhs.value)
+            .map(|value| VarUint { value })
+    }
+
+    /// Product of the value and `rhs`, or `None` on overflow
+    pub fn checked_mul(self, rhs: u64) -> Option<VarUint> {
+        self.value.checked_mul(rhs).map(|value| VarUint { value })
+    }
+
+    /// Product of the value and `rhs`, `u64::MAX` on overflow
+    pub fn saturating_mul(self, rhs: u64) -> VarUint {
+        VarUint {
+            value: self.value.saturating_mul(rhs),
+        }
+    }
+}
+
+/// Panics on overflow in debug builds and wraps in release builds, like the `u64` it wraps.
+/// Use `checked_add` for values read from the network
+impl core::ops::Add for VarUint {
+    type Output = VarUint;
+
+    fn add(self, rhs: VarUint) -> VarUint {
+        VarUint {
+            value: self.value + rhs.value,
+        }
+    }
+}
+
+/// Panics on underflow in debug builds and wraps in release builds, like the `u64` it wraps.
+/// Use `checked_sub` for values read from the network
+impl core::ops::Sub for VarUint {
+    type Output = VarUint;
+
+    fn sub(self, rhs: VarUint) -> VarUint {
+        VarUint {
+            value: self.value - rhs.value,
+        }
+    }
+}
+
+/// Printed as its value, the encoding being an implementation detail
+impl core::fmt::Debug for VarUint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.value, f)
+    }
+}
+
+impl core::fmt::Display for VarUint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl From<u64> for VarUint {
+    fn from(value: u64) -> VarUint {
+        VarUint { value }
+    }
+}
+
+impl From<VarUint> for u64 {
+    fn from(n: VarUint) -> u64 {
+        n.value
+    }
+}
+
+/// Fails on targets where `usize` is smaller than the value
+impl TryFrom<VarUint> for usize {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(n: VarUint) -> Result<usize, core::num::TryFromIntError> {
+        usize::try_from(n.value)
+    }
+}
+
 pub type Sha256Result = generic_array::GenericArray<u8, typenum::U32>;
 
+/// Lowercase hex of the hash, always two digits per byte
 pub fn hash_to_string(hash: &Sha256Result) -> String {
-    hash.iter()
-        .fold(String::new(), |acc, b| format!("{}{:02x}", acc, b))
+    use core::fmt::Write;
+
+    let mut s = String::with_capacity(2 * hash.len());
+    for b in hash.iter() {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    s
+}
+
+/// Parses the 64 hex digits written by `hash_to_string`, in either case
+pub fn hash_from_string(s: &str) -> Result<Sha256Result, Error> {
+    if s.len() != 64 {
+        return Err(Error::Message(format!(
+            "Hash string has {} characters, expected 64",
+            s.len()
+        )));
+    }
+    if let Some(i) = s.find(|c: char| !c.is_ascii_hexdigit()) {
+        return Err(Error::Message(format!(
+            "Invalid hex digit at index {} of hash string",
+            i
+        )));
+    }
+    let mut hash = Sha256Result::default();
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+    }
+    Ok(hash)
+}
+
+/// Reference to a transaction output: the hash of the transaction and the index of the output
+/// in it
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct OutPoint {
+    pub tx_hash: Sha256Result,
+    pub index: u32,
+}
+
+impl core::fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}:{}", hash_to_string(&self.tx_hash), self.index)
+    }
+}
+
+/// Kind of resource announced by an `InvVect`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum InvKind {
+    Tx,
+    Block,
+}
+
+impl TryFrom<u32> for InvKind {
+    type Error = Error;
+
+    fn try_from(kind: u32) -> Result<InvKind, Error> {
+        match kind {
+            0 => Ok(InvKind::Tx),
+            1 => Ok(InvKind::Block),
+            _ => Err(Error::Message(format!("Unknown inventory kind {}", kind))),
+        }
+    }
+}
+
+impl From<InvKind> for u32 {
+    fn from(kind: InvKind) -> u32 {
+        match kind {
+            InvKind::Tx => 0,
+            InvKind::Block => 1,
+        }
+    }
+}
+
+/// Inventory vector as carried by inv messages: a resource kind and its hash
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct InvVect {
+    pub kind: u32,
+    pub hash: Sha256Result,
+}
+
+impl InvVect {
+    pub fn new(kind: InvKind, hash: Sha256Result) -> InvVect {
+        InvVect {
+            kind: kind.into(),
+            hash,
+        }
+    }
+
+    /// Typed kind of the vector, failing on unknown kinds
+    pub fn inv_kind(&self) -> Result<InvKind, Error> {
+        InvKind::try_from(self.kind)
+    }
+}
+
+/// List of lists nested to an arbitrary depth, the dynamic counterpart of `Vec<Vec<...>>`.
+///
+/// It is encoded like a `Vec` of itself. Decoding, encoding and dropping use an explicit stack
+/// instead of recursion, so deep nesting can't overflow the native stack.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct NestedVec {
+    pub children: Vec<NestedVec>,
+}
+
+impl NestedVec {
+    /// Default maximum depth accepted when deserializing
+    pub const DEFAULT_MAX_DEPTH: usize = 1 << 16;
+
+    /// Number of nested levels, a childless list having a depth of 1
+    pub fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            max = core::cmp::max(max, depth);
+            stack.extend(node.children.iter().map(|child| (child, depth + 1)));
+        }
+        max
+    }
+}
+
+impl Drop for NestedVec {
+    fn drop(&mut self) {
+        let mut stack = core::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// Header of a block, the part of it covered by the proof of work
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_block: Sha256Result,
+    pub merkle_root: Sha256Result,
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    /// Double SHA-256 of the serialized header
+    #[cfg(feature = "sha2")]
+    pub fn hash(&self) -> Sha256Result {
+        use crate::serializer::Serialize;
+
+        self.double_hash()
+    }
+}
+
+/// Transaction script, serialized exactly like a `Vec<u8>`
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Script(pub Vec<u8>);
+
+impl Script {
+    pub fn new() -> Script {
+        Script(Vec::new())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Appends an opcode at the end of the script
+    pub fn push_opcode(&mut self, opcode: u8) {
+        self.0.push(opcode);
+    }
+}
+
+impl From<Vec<u8>> for Script {
+    fn from(bytes: Vec<u8>) -> Script {
+        Script(bytes)
+    }
+}
+
+/// Address of a peer as relayed in addr messages
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct NetworkAddress {
+    pub timestamp: u32,
+    pub services: u64,
+    pub addr: SocketAddr,
+}
+
+/// Target in its compact "bits" form: the high byte is the length in bytes of the target and
+/// the low 3 bytes its most significant bytes, with `0x00800000` as a sign bit
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct CompactTarget(pub u32);
+
+impl CompactTarget {
+    /// Expands to a 256 bits big endian target. Negative targets expand to 0 and targets that
+    /// don't fit in 256 bits saturate to the maximum.
+    pub fn to_target(&self) -> [u8; 32] {
+        let size = (self.0 >> 24) as isize;
+        let mantissa = self.0 & 0x007f_ffff;
+        let mut target = [0; 32];
+        if self.0 & 0x0080_0000 != 0 {
+            return target;
+        }
+        let mantissa_bytes = [
+            (mantissa >> 16) as u8,
+            (mantissa >> 8) as u8,
+            mantissa as u8,
+        ];
+        for (i, b) in mantissa_bytes.iter().enumerate() {
+            let position = 32 - size + i as isize;
+            if position < 0 {
+                if *b != 0 {
+                    return [0xff; 32];
+                }
+            } else if position < 32 {
+                target[position as usize] = *b;
+            }
+        }
+        target
+    }
+
+    /// Compacts a 256 bits big endian target, dropping all but its 3 most significant bytes
+    pub fn from_target(target: &[u8; 32]) -> CompactTarget {
+        let first = match target.iter().position(|b| *b != 0) {
+            Some(i) => i,
+            None => return CompactTarget(0),
+        };
+        let mut size = (32 - first) as u32;
+        let mut mantissa = (0..3).fold(0, |acc, i| {
+            (acc << 8) | *target.get(first + i).unwrap_or(&0) as u32
+        });
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        CompactTarget((size << 24) | mantissa)
+    }
+}
+
+/// Everything left in the buffer, for payloads whose length is given by an outer frame. It is
+/// written without any length prefix.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Remainder(pub Vec<u8>);
+
+/// Value present only when a flag read elsewhere (e.g. in a shared bitfield) is set. It is
+/// deserialized with that flag as context and written without any presence marker.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Flagged<T>(pub Option<T>);
+
+impl<T> Flagged<T> {
+    /// Value of the flag announcing this value
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Input of a transaction, spending a previous output
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TxInput {
+    pub previous_output: OutPoint,
+    pub script: Script,
+    pub sequence: u32,
+}
+
+/// Output of a transaction, locking an amount behind a script
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TxOutput {
+    pub value: u64,
+    pub script: Script,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Transaction {
+    pub version: u32,
+    pub flags: Vec<String>,
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+    pub locktime: u64,
+}
+
+impl Transaction {
+    /// Double SHA-256 of the serialized transaction
+    #[cfg(feature = "sha2")]
+    pub fn hash(&self) -> Sha256Result {
+        use crate::serializer::Serialize;
+
+        self.double_hash()
+    }
+}
+
+/// Bytes encoded as runs of a repeated byte, compact for sparse bitmap-like data
+///
+/// It is encoded as a `VarUint` number of runs, each run being a `VarUint` count followed by the
+/// repeated byte.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Rle(pub Vec<u8>);
+
+impl Rle {
+    /// Default maximum expanded size accepted when deserializing
+    pub const DEFAULT_MAX_SIZE: usize = 1 << 20;
+}
+
+/// Nonce sent in handshakes to detect connections to self, encoded as 8 raw bytes
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Nonce(pub [u8; 8]);
+
+impl Nonce {
+    /// Draws a nonce from the thread local random generator
+    #[cfg(feature = "rand")]
+    pub fn random() -> Nonce {
+        Nonce(rand::random())
+    }
+
+    /// Compares two nonces in constant time, to not leak through timing how much of a nonce
+    /// was guessed right
+    #[cfg(feature = "subtle")]
+    pub fn ct_eq(&self, other: &Nonce) -> bool {
+        subtle::ConstantTimeEq::ct_eq(&self.0[..], &other.0[..]).into()
+    }
+}
+
+/// Extensible record of values identified by a `u32` tag
+///
+/// It is encoded as a `VarUint` number of entries followed by each tag and its length
+/// prefixed value, in increasing tag order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlvBlock {
+    entries: BTreeMap<u32, Vec<u8>>,
+}
+
+impl TlvBlock {
+    pub fn new() -> TlvBlock {
+        TlvBlock::default()
+    }
+
+    /// Value of the entry with the given tag
+    pub fn get(&self, tag: u32) -> Option<&Vec<u8>> {
+        self.entries.get(&tag)
+    }
+
+    /// Sets the value of the entry with the given tag, returning its previous value
+    pub fn insert(&mut self, tag: u32, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.entries.insert(tag, value)
+    }
+
+    /// Entries in increasing tag order
+    pub fn iter(&self) -> impl Iterator<Item = (&u32, &Vec<u8>)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Signed 32 bits integer, zigzag encoded as a `VarUint` so that values close to zero take
+/// few bytes whatever their sign
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct VarInt32(pub i32);
+
+impl VarInt32 {
+    /// Maps signed values to unsigned ones alternating signs: 0, -1, 1, -2, 2...
+    pub fn zigzag(self) -> u32 {
+        ((self.0 << 1) ^ (self.0 >> 31)) as u32
+    }
+
+    pub fn from_zigzag(zigzag: u32) -> VarInt32 {
+        VarInt32((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+    }
+}
+
+/// Signed 64 bits integer, zigzag encoded as a `VarUint` like `VarInt32`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct VarInt {
+    pub value: i64,
+}
+
+impl VarInt {
+    /// Maps signed values to unsigned ones alternating signs: 0, -1, 1, -2, 2...
+    pub fn zigzag(self) -> u64 {
+        ((self.value << 1) ^ (self.value >> 63)) as u64
+    }
+
+    pub fn from_zigzag(zigzag: u64) -> VarInt {
+        VarInt {
+            value: (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64),
+        }
+    }
+}
+
+/// Hashes of blocks of a chain, densest near the tip, followed by the hash of the last block
+/// wanted or zero, used to find the last common block with a peer
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct BlockLocator {
+    pub hashes: Vec<Sha256Result>,
+    pub stop: Sha256Result,
+}
+
+impl BlockLocator {
+    /// Heights of the blocks of a locator for a chain of tip `tip`: the last 10 blocks, then
+    /// going back with a step doubling each time, always ending at the genesis block
+    pub fn heights(tip: u64) -> Vec<u64> {
+        let mut heights = Vec::new();
+        let mut height = tip;
+        let mut step = 1;
+        loop {
+            heights.push(height);
+            if height == 0 {
+                return heights;
+            }
+            if heights.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+    }
+
+    /// Builds the locator of a chain of tip `tip`, `hash_at` giving the hash of the block at a
+    /// height
+    pub fn new<F: FnMut(u64) -> Sha256Result>(
+        tip: u64,
+        hash_at: F,
+        stop: Sha256Result,
+    ) -> BlockLocator {
+        BlockLocator {
+            hashes: BlockLocator::heights(tip)
+                .into_iter()
+                .map(hash_at)
+                .collect(),
+            stop,
+        }
+    }
+}
+
+/// Cumulative proof of work of a chain, the expected number of hashes computed to produce it,
+/// encoded as a big endian `u128`
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Work(pub u128);
+
+impl Work {
+    /// Expected work to find a hash at most `target`, a 256 bits big endian number. That is
+    /// 2^256 / (target + 1), saturating to `u128::MAX`
+    pub fn from_target(target: &[u8; 32]) -> Work {
+        let word = |bytes: &[u8]| bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u128);
+        let (high, low) = (word(&target[..16]), word(&target[16..]));
+        if high == 0 && low == 0 {
+            return Work(u128::MAX);
+        }
+        if high == u128::MAX && low == u128::MAX {
+            return Work(1);
+        }
+        // 2^256 / (target + 1) computed as !target / (target + 1) + 1 to stay in 256 bits
+        let divisor = match low.checked_add(1) {
+            Some(low) => (high, low),
+            None => (high + 1, 0),
+        };
+        let (quotient_high, quotient_low) = div_u256((!high, !low), divisor);
+        if quotient_high != 0 {
+            return Work(u128::MAX);
+        }
+        Work(quotient_low.saturating_add(1))
+    }
+}
+
+/// Divides 256 bits numbers given as (high, low) halves, bit by bit
+fn div_u256(numerator: (u128, u128), divisor: (u128, u128)) -> (u128, u128) {
+    let mut quotient = (0, 0);
+    let mut remainder: (u128, u128) = (0, 0);
+    for i in (0..256).rev() {
+        let carry = remainder.0 >> 127;
+        let bit = if i >= 128 {
+            (numerator.0 >> (i - 128)) & 1
+        } else {
+            (numerator.1 >> i) & 1
+        };
+        remainder = (
+            (remainder.0 << 1) | (remainder.1 >> 127),
+            (remainder.1 << 1) | bit,
+        );
+        if carry == 1 || remainder >= divisor {
+            let (low, borrow) = remainder.1.overflowing_sub(divisor.1);
+            remainder = (
+                remainder
+                    .0
+                    .wrapping_sub(divisor.0)
+                    .wrapping_sub(borrow as u128),
+                low,
+            );
+            if i >= 128 {
+                quotient.0 |= 1 << (i - 128);
+            } else {
+                quotient.1 |= 1 << i;
+            }
+        }
+    }
+    quotient
+}
+
+/// Saturates to `u128::MAX`, like `Work::from_target`, so that summing the work of a chain
+/// can't wrap around to a smaller value
+impl core::ops::Add for Work {
+    type Output = Work;
+
+    fn add(self, other: Work) -> Work {
+        Work(self.0.saturating_add(other.0))
+    }
+}
+
+/// Saturates to `u128::MAX`, like `Add`
+impl core::ops::AddAssign for Work {
+    fn add_assign(&mut self, other: Work) {
+        self.0 = self.0.saturating_add(other.0);
+    }
+}
+
+/// List of hashes written with each repeated hash replaced by a reference to its first
+/// occurrence, for payloads with many repeated hashes
+///
+/// It is encoded as a `VarUint` count followed by a `VarUint` per hash: 0 for a hash not seen
+/// before, followed by its 32 bytes, or else 1 plus the index of the hash among the distinct
+/// hashes already written.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct DedupHashes(pub Vec<Sha256Result>);
+
+/// Minimum fee rate, in base units per kilobyte, of the transactions a peer wants to be
+/// announced, encoded as a `u64`
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct FeeFilter(pub u64);
+
+impl core::fmt::Display for FeeFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} per kB", self.0)
+    }
+}
+
+/// Seconds since the Unix epoch, encoded as a `u64`
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Timestamp(pub u64);
+
+#[cfg(feature = "std")]
+impl From<Timestamp> for std::time::SystemTime {
+    fn from(timestamp: Timestamp) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + core::time::Duration::from_secs(timestamp.0)
+    }
+}
+
+/// Truncated to the second, failing for times before the epoch
+#[cfg(feature = "std")]
+impl TryFrom<std::time::SystemTime> for Timestamp {
+    type Error = std::time::SystemTimeError;
+
+    fn try_from(time: std::time::SystemTime) -> Result<Timestamp, std::time::SystemTimeError> {
+        time.duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| Timestamp(elapsed.as_secs()))
+    }
+}
+
+/// Decodes the payload of a message kind registered in a `TaggedStream`
+pub type MessageDecoder = fn(Vec<u8>) -> Result<Box<dyn Message>, Error>;
+
+/// Message of a `TaggedStream` with its tag
+pub type TaggedMessage = (u8, Box<dyn Message>);
+
+fn decode_boxed<T: Message + 'static>(payload: Vec<u8>) -> Result<Box<dyn Message>, Error> {
+    Ok(Box::new(T::decode(payload)?))
+}
+
+/// Stream of messages of different kinds, each written as a `u8` tag giving its kind, a
+/// `VarUint` length and its payload
+#[derive(Default)]
+pub struct TaggedStream {
+    decoders: BTreeMap<u8, MessageDecoder>,
+}
+
+impl TaggedStream {
+    pub fn new() -> TaggedStream {
+        TaggedStream::default()
+    }
+
+    /// Decodes messages with tag `tag` as a `T`
+    pub fn register<T: Message + 'static>(&mut self, tag: u8) {
+        self.decoders.insert(tag, decode_boxed::<T>);
+    }
+
+    /// Writes the messages with their tag
+    pub fn encode(messages: &[TaggedMessage]) -> Vec<u8> {
+        let mut v = Vec::new();
+        for (tag, message) in messages {
+            let payload = message.encode();
+            v.push(*tag);
+            v.extend_from_slice(
+                &VarUint {
+                    value: payload.len() as u64,
+                }
+                .encode(),
+            );
+            v.extend_from_slice(&payload);
+        }
+        v
+    }
+
+    /// Reads all the messages of `bytes`, failing on a tag that was not registered
+    pub fn decode(&self, mut bytes: &[u8]) -> Result<Vec<TaggedMessage>, Error> {
+        let mut messages = Vec::new();
+        while let Some((tag, rest)) = bytes.split_first() {
+            let decoder = match self.decoders.get(tag) {
+                Some(decoder) => decoder,
+                None => return Err(Error::Message(format!("Unregistered message tag {}", tag))),
+            };
+            let (length, prefix) = VarUint::from_slice(rest)?;
+            let rest = &rest[prefix..];
+            if length.value > rest.len() as u64 {
+                return Err(Error::BufferTooShort(
+                    "TaggedStream message",
+                    length.value as usize,
+                    rest.len(),
+                ));
+            }
+            let (payload, rest) = rest.split_at(length.value as usize);
+            match decoder(payload.to_vec()) {
+                Ok(message) => messages.push((*tag, message)),
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "In reading message of tag {}: {}",
+                        tag, e
+                    )));
+                }
+            }
+            bytes = rest;
+        }
+        Ok(messages)
+    }
+}
+
+/// Keepalive request carrying a nonce that the peer echoes back in a `Pong`, encoded as a `u64`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Ping(pub u64);
+
+impl Ping {
+    /// Ping with a nonce drawn from the thread local random generator
+    #[cfg(feature = "rand")]
+    pub fn random() -> Ping {
+        Ping(rand::random())
+    }
+
+    /// The `Pong` answering this ping
+    pub fn pong(&self) -> Pong {
+        Pong(self.0)
+    }
+}
+
+/// Answer to a `Ping`, echoing its nonce, encoded as a `u64`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Pong(pub u64);
+
+impl Pong {
+    /// Whether this pong answers `ping`
+    pub fn matches(&self, ping: &Ping) -> bool {
+        self.0 == ping.0
+    }
+}
+
+/// Answer to a message that was refused, naming the kind of the refused `message`, with a
+/// `code` and human readable `reason` and the hash of the refused object if any
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Reject {
+    pub message: String,
+    pub code: u8,
+    pub reason: String,
+    pub data: Option<Sha256Result>,
+}
+
+/// First message sent on a connection, describing the sending node
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct VersionMessage {
+    pub version: u32,
+    pub services: u64,
+    pub timestamp: u64,
+    pub addr_recv: SocketAddr,
+    pub addr_from: SocketAddr,
+    pub nonce: Nonce,
+    pub user_agent: String,
+    pub start_height: u32,
+    pub relay: bool,
+}
+
+/// Block header followed by the transactions it commits to through its merkle root
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+}
+
+impl Block {
+    /// Hash of the block, which is the hash of its header
+    #[cfg(feature = "sha2")]
+    pub fn hash(&self) -> Sha256Result {
+        self.header.hash()
+    }
+
+    /// Merkle root of the transaction hashes. Each level is reduced by double hashing pairs of
+    /// hashes, the last hash of an odd level being paired with itself. A block without
+    /// transactions has the zero hash as root.
+    ///
+    /// This pairing gives `[a, b, c]` and `[a, b, c, c]` the same root, see `validate` to reject
+    /// blocks mutated by repeating transactions
+    #[cfg(feature = "sha2")]
+    pub fn merkle_root(&self) -> Sha256Result {
+        self.merkle_root_mutated().0
+    }
+
+    /// Merkle root of the transaction hashes, and whether a level has two equal sibling hashes
+    #[cfg(feature = "sha2")]
+    fn merkle_root_mutated(&self) -> (Sha256Result, bool) {
+        use sha2::{Digest, Sha256};
+
+        let mut level: Vec<Sha256Result> =
+            self.transactions.iter().map(Transaction::hash).collect();
+        if level.is_empty() {
+            return (Sha256Result::default(), false);
+        }
+        let mut mutated = false;
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    let right = match pair.get(1) {
+                        Some(right) => {
+                            mutated |= *right == pair[0];
+                            right
+                        }
+                        None => &pair[0],
+                    };
+                    let mut hasher = Sha256::new();
+                    hasher.input(pair[0]);
+                    hasher.input(right);
+                    Sha256::digest(&hasher.result())
+                })
+                .collect();
+        }
+        (level[0], mutated)
+    }
+
+    /// Checks that the merkle root of the transactions is the one of the header. Blocks where two
+    /// sibling hashes of the merkle tree are equal are rejected, as they share their root with
+    /// the block without the repeated transactions (CVE-2012-2459)
+    #[cfg(feature = "sha2")]
+    pub fn validate(&self) -> Result<(), Error> {
+        let (merkle_root, mutated) = self.merkle_root_mutated();
+        if mutated {
+            return Err(Error::Message(String::from(
+                "Block merkle tree has two equal sibling hashes",
+            )));
+        }
+        if merkle_root != self.header.merkle_root {
+            return Err(Error::Message(format!(
+                "Block merkle root {} doesn't match the one of its header {}",
+                hash_to_string(&merkle_root),
+                hash_to_string(&self.header.merkle_root)
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::hash_to_string;
+    use crate::types::BlockHeader;
+    use crate::types::BlockLocator;
+    use crate::types::CompactTarget;
+    use crate::types::DedupHashes;
+    use crate::types::FeeFilter;
+    use crate::types::InvKind;
+    use crate::types::InvVect;
+    use crate::types::NetworkAddress;
+    use crate::types::Nonce;
+    use crate::types::OutPoint;
+    use crate::types::Script;
+    use crate::types::TaggedMessage;
+    use crate::types::TaggedStream;
+    use crate::types::Timestamp;
+    use crate::types::TlvBlock;
+    use crate::types::Transaction;
+    use crate::types::TxInput;
+    use crate::types::TxOutput;
+    use crate::types::VarInt;
+    use crate::types::VarInt32;
+    use crate::types::VarUint;
+    use crate::types::VersionMessage;
+    use crate::types::Work;
+    use crate::Deserialize;
+    use crate::Deserializer;
+    use crate::Serialize;
     use crate::Sha256Result;
+    use bytes::BytesMut;
+    use std::convert::TryFrom;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
     #[test]
     fn zero_hash() {
@@ -24,4 +937,683 @@ mod tests {
             String::from("0000000000000000000000000000000000000000000000000000000000000000")
         )
     }
+
+    #[test]
+    fn varuint_conversions() {
+        let n = VarUint::from(300);
+        assert_eq!(300, n.value);
+        assert_eq!(300, u64::from(n));
+        assert_eq!(300, usize::try_from(VarUint::from(300)).unwrap());
+        let max = usize::try_from(VarUint::from(u64::MAX));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(usize::MAX, max.unwrap());
+        #[cfg(not(target_pointer_width = "64"))]
+        assert!(max.is_err());
+    }
+
+    #[test]
+    fn varuint_mul() {
+        let half = VarUint::from(1 << 32);
+        assert_eq!(Some(1 << 63), half.checked_mul(1 << 31).map(u64::from));
+        assert!(VarUint::from(1 << 32).checked_mul(1 << 32).is_none());
+        assert_eq!(
+            u64::MAX,
+            VarUint::from(1 << 32).saturating_mul(1 << 32).value
+        );
+        assert_eq!(u64::MAX, VarUint::from(u64::MAX).saturating_mul(2).value);
+    }
+
+    #[test]
+    fn varuint_ordering() {
+        let mut v: Vec<VarUint> = [300, 2, 70000, 0]
+            .iter()
+            .map(|n| VarUint::from(*n))
+            .collect();
+        v.sort();
+        assert_eq!(
+            vec![0, 2, 300, 70000],
+            v.into_iter().map(u64::from).collect::<Vec<_>>()
+        );
+        assert!(VarUint { value: 5 } < VarUint::from(6));
+        assert!(VarUint { value: 0xFD } > VarUint::from(0xFC));
+        assert_eq!(VarUint::from(7), VarUint::from(3) + VarUint::from(4));
+        assert_eq!(VarUint::from(3), VarUint::from(7) - VarUint::from(4));
+        assert_eq!(None, VarUint::from(3).checked_sub(VarUint::from(4)));
+        assert_eq!(None, VarUint::from(u64::MAX).checked_add(VarUint::from(1)));
+        let (a, b) = (VarUint::from(7), VarUint::from(4));
+        assert_eq!(VarUint::from(11), a + b);
+        assert_eq!(VarUint::from(3), a - b);
+        assert!(a > b && a - b < b);
+        assert_eq!(Some(a + b), a.checked_add(b));
+        assert_eq!("300", VarUint::from(300).to_string());
+        assert_eq!("300", format!("{:?}", VarUint::from(300)));
+    }
+
+    #[test]
+    fn hash_from_string() {
+        let mut bytes = [0xab; 32];
+        bytes[0] = 0x00;
+        bytes[1] = 0x0f;
+        let hash = Sha256Result::from(bytes);
+        let s = hash_to_string(&hash);
+        assert_eq!(hash, crate::types::hash_from_string(&s).unwrap());
+        assert_eq!(
+            hash,
+            crate::types::hash_from_string(&s.to_uppercase()).unwrap()
+        );
+        assert!(crate::types::hash_from_string(&s[..63]).is_err());
+        assert!(crate::types::hash_from_string(&s.replace("0f", "0g")).is_err());
+        assert!(crate::types::hash_from_string(&s.replace("0f", "+f")).is_err());
+    }
+
+    #[test]
+    fn hash_with_small_bytes() {
+        let mut bytes = [0xab; 32];
+        bytes[0] = 0x00;
+        bytes[1] = 0x0f;
+        bytes[31] = 0x0a;
+        let s = hash_to_string(&Sha256Result::from(bytes));
+        assert_eq!(64, s.len());
+        assert_eq!(
+            "000fababababababababababababababababababababababababababababab0a",
+            s
+        );
+    }
+
+    #[test]
+    fn outpoint_round_trip() {
+        let outpoint = OutPoint {
+            tx_hash: Sha256Result::from([7; 32]),
+            index: 258,
+        };
+        let raw = outpoint.serialize();
+        assert_eq!(36, raw.len());
+        assert_eq!(vec![0, 0, 1, 2], raw[32..].to_vec());
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(outpoint, OutPoint::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn outpoint_display() {
+        let outpoint = OutPoint {
+            tx_hash: Sha256Result::from([0xab; 32]),
+            index: 3,
+        };
+        assert_eq!(format!("{}:3", "ab".repeat(32)), outpoint.to_string());
+    }
+
+    #[test]
+    fn inv_vect_round_trip() {
+        let inv = vec![
+            InvVect::new(InvKind::Tx, Sha256Result::from([1; 32])),
+            InvVect::new(InvKind::Block, Sha256Result::from([2; 32])),
+        ];
+        let raw = inv.serialize();
+        assert_eq!(1 + 2 * 36, raw.len());
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        let decoded: Vec<InvVect> = Vec::deserialize(&mut de).unwrap();
+        assert_eq!(inv, decoded);
+        assert_eq!(InvKind::Block, decoded[1].inv_kind().unwrap());
+    }
+
+    #[test]
+    fn inv_kind_from_u32() {
+        assert_eq!(InvKind::Tx, InvKind::try_from(0).unwrap());
+        assert_eq!(InvKind::Block, InvKind::try_from(1).unwrap());
+        assert!(InvKind::try_from(2).is_err());
+    }
+
+    fn block_header() -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block: Sha256Result::from([0; 32]),
+            merkle_root: Sha256Result::from([1; 32]),
+            timestamp: 1_566_000_000,
+            bits: 0x1d00ffff,
+            nonce: 42,
+        }
+    }
+
+    #[test]
+    fn block_header_round_trip() {
+        let header = block_header();
+        let raw = header.serialize();
+        assert_eq!(88, raw.len());
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(header, BlockHeader::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn block_header_into_slice() {
+        let header = block_header();
+        let mut buf = [0; 88];
+        assert_eq!(88, header.serialize_into_slice(&mut buf).unwrap());
+        assert_eq!(header.serialize().to_vec(), buf.to_vec());
+
+        let mut small = [0; 87];
+        match header.serialize_into_slice(&mut small) {
+            Err(crate::Error::LengthExceeded(88, 87)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!([0; 87].to_vec(), small.to_vec());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn block_header_hash() {
+        assert_eq!(
+            "d155f2cbf67b034d42470c2866846b49b5758948794ac474d8340d98e661b93a",
+            hash_to_string(&block_header().hash())
+        );
+    }
+
+    #[test]
+    fn script_round_trip() {
+        let script = Script::from(vec![0x76, 0xa9, 0x88, 0xac]);
+        let raw = script.serialize();
+        assert_eq!(script.0.serialize(), raw);
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(script, Script::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn script_helpers() {
+        let mut script = Script::new();
+        assert!(script.is_empty());
+        script.push_opcode(0x76);
+        script.push_opcode(0xac);
+        assert!(!script.is_empty());
+        assert_eq!(2, script.len());
+        assert_eq!(&[0x76, 0xac], script.as_bytes());
+    }
+
+    #[test]
+    fn network_address_round_trip() {
+        let addresses = vec![
+            NetworkAddress {
+                timestamp: 1_566_000_000,
+                services: 1,
+                addr: SocketAddr::new(
+                    IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                    4224,
+                ),
+            },
+            NetworkAddress {
+                timestamp: 1_566_000_001,
+                services: 0,
+                addr: SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 8333),
+            },
+        ];
+        let raw = addresses.serialize();
+        assert_eq!(1 + 2 * 30, raw.len());
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        let decoded: Vec<NetworkAddress> = Vec::deserialize(&mut de).unwrap();
+        assert_eq!(addresses, decoded);
+    }
+
+    #[test]
+    fn network_address_ipv4_mapped() {
+        let ip = Ipv4Addr::new(192, 168, 1, 42);
+        let address = NetworkAddress {
+            timestamp: 7,
+            services: 1,
+            addr: SocketAddr::new(IpAddr::V4(ip), 4224),
+        };
+        let raw = address.serialize();
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 168, 1, 42, 0x10, 0x80],
+            raw[12..].to_vec()
+        );
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        let decoded = NetworkAddress::deserialize(&mut de).unwrap();
+        assert_eq!(SocketAddr::new(IpAddr::V4(ip), 4224), decoded.addr);
+    }
+
+    #[test]
+    fn compact_target_round_trip() {
+        let bits = CompactTarget(0x1d00ffff);
+        let raw = bits.serialize();
+        assert_eq!(vec![0x1d, 0x00, 0xff, 0xff], raw);
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(bits, CompactTarget::deserialize(&mut de).unwrap());
+
+        let mut target = [0; 32];
+        target[4] = 0xff;
+        target[5] = 0xff;
+        assert_eq!(target, bits.to_target());
+        assert_eq!(bits, CompactTarget::from_target(&target));
+
+        let small = CompactTarget(0x0212_3400);
+        assert_eq!(small, CompactTarget::from_target(&small.to_target()));
+        assert_eq!(CompactTarget(0), CompactTarget::from_target(&[0; 32]));
+    }
+
+    #[test]
+    fn compact_target_max() {
+        let bits = CompactTarget::from_target(&[0xff; 32]);
+        assert_eq!(CompactTarget(0x2100ffff), bits);
+        let mut target = [0; 32];
+        target[0] = 0xff;
+        target[1] = 0xff;
+        assert_eq!(target, bits.to_target());
+        assert_eq!(bits, CompactTarget::from_target(&bits.to_target()));
+
+        assert_eq!([0xff; 32], CompactTarget(0x2200ffff).to_target());
+        assert_eq!([0; 32], CompactTarget(0x1d80ffff).to_target());
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            version: 1,
+            flags: vec![String::from("a")],
+            inputs: vec![TxInput {
+                previous_output: OutPoint {
+                    tx_hash: Sha256Result::from([2; 32]),
+                    index: 0,
+                },
+                script: Script::from(vec![0x51]),
+                sequence: 0xffff_ffff,
+            }],
+            outputs: vec![TxOutput {
+                value: 50,
+                script: Script::new(),
+            }],
+            locktime: 0,
+        }
+    }
+
+    #[test]
+    fn transaction_round_trip() {
+        let mut expected = vec![0, 0, 0, 1, 1, 1, b'a', 1];
+        expected.extend_from_slice(&[2; 32]);
+        expected.extend_from_slice(&[0, 0, 0, 0, 1, 0x51, 0xff, 0xff, 0xff, 0xff]);
+        expected.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 50, 0]);
+        expected.extend_from_slice(&[0; 8]);
+
+        let tx = transaction();
+        assert_eq!(expected, tx.serialize());
+        let mut de = Deserializer::new(BytesMut::from(expected));
+        assert_eq!(tx, Transaction::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn transaction_hash() {
+        assert_eq!(
+            "c18712e2d4b6add718db626a03e1b6640c20ef93ab2be1636155e83067ab2f3c",
+            hash_to_string(&transaction().hash())
+        );
+    }
+
+    #[test]
+    fn nonce() {
+        let nonce = Nonce([1, 2, 3, 4, 5, 6, 7, 8]);
+        let v = nonce.serialize();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(nonce, Nonce::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "subtle")]
+    #[test]
+    fn nonce_ct_eq() {
+        let nonce = Nonce([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(nonce.ct_eq(&Nonce([1, 2, 3, 4, 5, 6, 7, 8])));
+        assert!(!nonce.ct_eq(&Nonce([1, 2, 3, 4, 5, 6, 7, 9])));
+        assert!(!nonce.ct_eq(&Nonce([0, 2, 3, 4, 5, 6, 7, 8])));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn nonce_random() {
+        assert_ne!(Nonce::random(), Nonce::random());
+    }
+
+    #[test]
+    fn tlv_block() {
+        let mut block = TlvBlock::new();
+        assert_eq!(None, block.insert(7, vec![1, 2]));
+        assert_eq!(None, block.insert(2, Vec::new()));
+        assert_eq!(Some(&vec![1, 2]), block.get(7));
+        let v = block.serialize();
+        assert_eq!(vec![2, 0, 0, 0, 2, 0, 0, 0, 0, 7, 2, 1, 2], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(block, TlvBlock::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn tlv_block_duplicate_tag() {
+        let v = vec![2, 0, 0, 0, 7, 1, 1, 0, 0, 0, 7, 1, 2];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(TlvBlock::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn var_int() {
+        for (value, encoded) in [
+            (0, vec![0]),
+            (-1, vec![1]),
+            (1, vec![2]),
+            (
+                i64::MAX,
+                vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE],
+            ),
+            (
+                i64::MIN,
+                vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+        ]
+        .iter()
+        {
+            let v = VarInt { value: *value }.serialize();
+            assert_eq!(encoded, &v.to_vec());
+            let mut de = Deserializer::new(BytesMut::from(v));
+            assert_eq!(*value, VarInt::deserialize(&mut de).unwrap().value);
+        }
+    }
+
+    #[test]
+    fn var_int32() {
+        for (value, encoded) in [
+            (0, vec![0]),
+            (-1, vec![1]),
+            (1, vec![2]),
+            (-64, vec![127]),
+            (i32::MAX, vec![0xFE, 0xFF, 0xFF, 0xFF, 0xFE]),
+            (i32::MIN, vec![0xFE, 0xFF, 0xFF, 0xFF, 0xFF]),
+        ]
+        .iter()
+        {
+            let v = VarInt32(*value).serialize();
+            assert_eq!(encoded, &v.to_vec());
+            let mut de = Deserializer::new(BytesMut::from(v));
+            assert_eq!(VarInt32(*value), VarInt32::deserialize(&mut de).unwrap());
+        }
+
+        let v = vec![0xFF, 0, 0, 0, 1, 0, 0, 0, 0];
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(VarInt32::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn block_locator() {
+        let locator = BlockLocator {
+            hashes: vec![Sha256Result::from([1; 32]), Sha256Result::from([2; 32])],
+            stop: Sha256Result::from([0; 32]),
+        };
+        let v = locator.serialize();
+        assert_eq!(1 + 3 * 32, v.len());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(locator, BlockLocator::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn block_locator_heights() {
+        assert_eq!(vec![0], BlockLocator::heights(0));
+        assert_eq!(vec![3, 2, 1, 0], BlockLocator::heights(3));
+        assert_eq!(
+            vec![100, 99, 98, 97, 96, 95, 94, 93, 92, 91, 89, 85, 77, 61, 29, 0],
+            BlockLocator::heights(100)
+        );
+
+        let locator = BlockLocator::new(
+            3,
+            |height| Sha256Result::from([height as u8; 32]),
+            Sha256Result::from([0; 32]),
+        );
+        assert_eq!(Sha256Result::from([3; 32]), locator.hashes[0]);
+        assert_eq!(Sha256Result::from([0; 32]), locator.hashes[3]);
+    }
+
+    #[test]
+    fn var_uint_from_slice() {
+        assert_eq!(42, VarUint::from_slice(&[42, 7]).unwrap().0.value);
+        assert_eq!(1, VarUint::from_slice(&[42, 7]).unwrap().1);
+        let (n, length) = VarUint::from_slice(&[0xFD, 0x12, 0x34]).unwrap();
+        assert_eq!((0x1234, 3), (n.value, length));
+        let (n, length) = VarUint::from_slice(&[0xFE, 0x12, 0x34, 0x56, 0x78]).unwrap();
+        assert_eq!((0x12345678, 5), (n.value, length));
+        let (n, length) =
+            VarUint::from_slice(&[0xFF, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0]).unwrap();
+        assert_eq!((0x123456789ABCDEF0, 9), (n.value, length));
+
+        assert!(VarUint::from_slice(&[]).is_err());
+        assert!(VarUint::from_slice(&[0xFE, 0x12, 0x34]).is_err());
+        assert!(VarUint::from_slice(&[0xFD, 0, 42]).is_err());
+        assert!(VarUint::from_slice(&[0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn work() {
+        let work = Work(0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10);
+        let v = work.serialize();
+        assert_eq!((1..=16).collect::<Vec<u8>>(), v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(work, Work::deserialize(&mut de).unwrap());
+
+        let mut total = Work(1);
+        total += Work(2);
+        assert_eq!(Work(4), total + Work(1));
+        assert_eq!(Work(u128::MAX), Work(u128::MAX) + Work(1));
+        total += Work(u128::MAX);
+        assert_eq!(Work(u128::MAX), total);
+    }
+
+    #[test]
+    fn work_from_target() {
+        let difficulty_1 = CompactTarget(0x1d00_ffff).to_target();
+        assert_eq!(Work(0x1_0001_0001), Work::from_target(&difficulty_1));
+        let mut half = [0; 32];
+        half[0] = 0x80;
+        assert_eq!(Work(1), Work::from_target(&[0xff; 32]));
+        assert_eq!(Work(1), Work::from_target(&half));
+        half[0] = 0x7f;
+        half[1..].copy_from_slice(&[0xff; 31]);
+        assert_eq!(Work(2), Work::from_target(&half));
+        assert_eq!(Work(u128::MAX), Work::from_target(&[0; 32]));
+    }
+
+    #[test]
+    fn dedup_hashes() {
+        let a = Sha256Result::from([0xAA; 32]);
+        let b = Sha256Result::from([0xBB; 32]);
+        let hashes = DedupHashes(vec![a, b, a, a, b]);
+        let v = hashes.serialize();
+        assert_eq!(1 + 2 * 33 + 3, v.len());
+        assert_eq!(vec![1, 1, 2], v[67..].to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(hashes, DedupHashes::deserialize(&mut de).unwrap());
+
+        let mut de = Deserializer::new(BytesMut::from(vec![1, 1]));
+        assert!(DedupHashes::deserialize(&mut de).is_err());
+
+        let mut v = vec![2, 0];
+        v.extend_from_slice(&a);
+        v.push(0);
+        v.extend_from_slice(&a);
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert!(DedupHashes::deserialize(&mut de).is_err());
+
+        let v = vec![3, 1, 1, 1];
+        let mut de = Deserializer::new(BytesMut::from(v)).with_max_collection_len(2);
+        match DedupHashes::deserialize(&mut de) {
+            Err(crate::Error::LengthExceeded(3, 2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn fee_filter() {
+        let filter = FeeFilter(1000);
+        let v = filter.serialize();
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0x03, 0xE8], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(filter, FeeFilter::deserialize(&mut de).unwrap());
+        assert_eq!("1000 per kB", filter.to_string());
+    }
+
+    #[test]
+    fn timestamp() {
+        let timestamp = Timestamp(1_570_023_462);
+        let v = timestamp.serialize();
+        assert_eq!(vec![0, 0, 0, 0, 0x5D, 0x94, 0xA8, 0x26], v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(timestamp, Timestamp::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timestamp_system_time() {
+        let timestamp = Timestamp(1_570_023_462);
+        let time = std::time::SystemTime::from(timestamp);
+        assert_eq!(timestamp, Timestamp::try_from(time).unwrap());
+        let later = time + std::time::Duration::from_millis(1500);
+        assert_eq!(
+            Timestamp(1_570_023_463),
+            Timestamp::try_from(later).unwrap()
+        );
+        let before_epoch = std::time::UNIX_EPOCH - std::time::Duration::from_secs(1);
+        assert!(Timestamp::try_from(before_epoch).is_err());
+    }
+
+    #[test]
+    fn tagged_stream() {
+        let mut stream = TaggedStream::new();
+        stream.register::<String>(1);
+        stream.register::<u32>(2);
+
+        let messages: Vec<TaggedMessage> = vec![
+            (1, Box::new(String::from("ab"))),
+            (2, Box::new(0x0102_0304_u32)),
+        ];
+        let bytes = TaggedStream::encode(&messages);
+        assert_eq!(vec![1, 3, 2, b'a', b'b', 2, 4, 1, 2, 3, 4], bytes);
+        let decoded = stream.decode(&bytes).unwrap();
+        assert_eq!(2, decoded.len());
+        assert_eq!(1, decoded[0].0);
+        assert_eq!(
+            Some(&String::from("ab")),
+            decoded[0].1.downcast_ref::<String>()
+        );
+        assert_eq!(None, decoded[0].1.downcast_ref::<u32>());
+        assert_eq!(2, decoded[1].0);
+        assert_eq!(
+            Some(&0x0102_0304_u32),
+            decoded[1].1.as_any().downcast_ref::<u32>()
+        );
+
+        assert!(stream.decode(&[3, 0]).is_err());
+        assert!(stream.decode(&[2, 4, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn version_message() {
+        let message = VersionMessage {
+            version: 1,
+            services: 1,
+            timestamp: 0x5D94_A826,
+            addr_recv: "[2001:db8::1]:4224".parse().unwrap(),
+            addr_from: "[2001:db8::2]:4224".parse().unwrap(),
+            nonce: Nonce([1, 2, 3, 4, 5, 6, 7, 8]),
+            user_agent: "ensicoin".to_string(),
+            start_height: 10,
+            relay: true,
+        };
+        let mut expected = vec![
+            0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0x5D, 0x94, 0xA8, 0x26,
+        ];
+        for last in [1, 2].iter() {
+            expected.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            expected.extend_from_slice(&[*last, 0x10, 0x80]);
+        }
+        expected.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 8]);
+        expected.extend_from_slice(b"ensicoin");
+        expected.extend_from_slice(&[0, 0, 0, 10, 1]);
+        let v = message.serialize();
+        assert_eq!(expected, v.to_vec());
+        let mut de = Deserializer::new(BytesMut::from(v));
+        assert_eq!(message, VersionMessage::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "sha2")]
+    fn transactions(count: u64) -> Vec<Transaction> {
+        (0..count)
+            .map(|locktime| Transaction {
+                locktime,
+                ..transaction()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn block_round_trip() {
+        use crate::types::Block;
+        use sha2::{Digest, Sha256};
+
+        let mut block = Block {
+            header: block_header(),
+            transactions: transactions(3),
+        };
+        block.header.merkle_root = block.merkle_root();
+        let double_hash = |bytes: &[u8]| Sha256::digest(&Sha256::digest(bytes));
+        let hashes: Vec<Sha256Result> = transactions(3).iter().map(Transaction::hash).collect();
+        let left = double_hash(&[hashes[0], hashes[1]].concat());
+        let right = double_hash(&[hashes[2], hashes[2]].concat());
+        assert_eq!(
+            double_hash(&[left, right].concat()),
+            block.header.merkle_root
+        );
+        block.validate().unwrap();
+
+        let mut expected = block.header.serialize().to_vec();
+        expected.push(3);
+        for transaction in transactions(3) {
+            expected.extend_from_slice(&transaction.serialize());
+        }
+        let raw = block.serialize();
+        assert_eq!(expected, raw.to_vec());
+        assert_eq!(raw.len(), block.serialized_size());
+        let mut into = Vec::new();
+        block.serialize_into(&mut into);
+        assert_eq!(raw.to_vec(), into);
+        let mut de = Deserializer::new(BytesMut::from(raw));
+        assert_eq!(block, Block::deserialize(&mut de).unwrap());
+        assert_eq!(block.header.hash(), block.hash());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn block_merkle_root_mismatch() {
+        use crate::types::Block;
+
+        let block = Block {
+            header: block_header(),
+            transactions: vec![transaction()],
+        };
+        assert_eq!(transaction().hash(), block.merkle_root());
+        let error = block.validate().unwrap_err().to_string();
+        assert!(error.contains("merkle root"), "{}", error);
+        // Decoding doesn't depend on the sha2 feature, so it doesn't check the merkle root
+        let mut de = Deserializer::new(BytesMut::from(block.serialize()));
+        assert_eq!(block, Block::deserialize(&mut de).unwrap());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn block_mutated_merkle_tree() {
+        use crate::types::Block;
+
+        let mut block = Block {
+            header: block_header(),
+            transactions: transactions(3),
+        };
+        block.header.merkle_root = block.merkle_root();
+        let mut mutated = block.clone();
+        mutated.transactions.push(mutated.transactions[2].clone());
+        assert_eq!(block.merkle_root(), mutated.merkle_root());
+        block.validate().unwrap();
+        let error = mutated.validate().unwrap_err().to_string();
+        assert!(error.contains("equal sibling hashes"), "{}", error);
+    }
 }
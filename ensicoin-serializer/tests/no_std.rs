@@ -0,0 +1,37 @@
+//! Uses the core types with only `alloc`, to be run with `--no-default-features` to check that
+//! they don't depend on `std`
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use bytes::BytesMut;
+use ensicoin_serializer::{Deserialize, Deserializer, Serialize, VarUint};
+
+fn round_trip<T: Serialize + Deserialize>(value: &T) -> T {
+    let mut de = Deserializer::new(BytesMut::from(value.serialize()));
+    let decoded = T::deserialize(&mut de).unwrap();
+    assert!(de.is_empty());
+    decoded
+}
+
+#[test]
+fn primitives() {
+    assert_eq!(0xDEADBEEF, round_trip(&0xDEADBEEF_u32));
+    assert_eq!(-42, round_trip(&-42_i64));
+    assert!(round_trip(&true));
+}
+
+#[test]
+fn varuint() {
+    assert_eq!(0xFFFF, round_trip(&VarUint { value: 0xFFFF }).value);
+}
+
+#[test]
+fn collections() {
+    let strings: Vec<String> = vec!["ensi".to_string(), "coin".to_string()];
+    assert_eq!(strings, round_trip(&strings));
+    assert_eq!(Some(7_u8), round_trip(&Some(7_u8)));
+}
@@ -0,0 +1,137 @@
+//! Counts the allocations made when serializing a struct of 20 fields, field by field into
+//! separate buffers as the derive used to do, and with `serialize_into` into a single buffer.
+//! Run with `cargo bench -p ensicoin_serializer_derive`.
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+use ensicoin_serializer::Serialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[derive(Serialize)]
+struct Wide {
+    f0: u8,
+    f1: u16,
+    f2: u32,
+    f3: u64,
+    f4: i8,
+    f5: i16,
+    f6: i32,
+    f7: i64,
+    f8: bool,
+    f9: u64,
+    f10: u8,
+    f11: u16,
+    f12: u32,
+    f13: u64,
+    f14: i8,
+    f15: i16,
+    f16: i32,
+    f17: i64,
+    f18: bool,
+    f19: u32,
+}
+
+fn wide() -> Wide {
+    Wide {
+        f0: 0,
+        f1: 1,
+        f2: 2,
+        f3: 3,
+        f4: -4,
+        f5: -5,
+        f6: -6,
+        f7: -7,
+        f8: true,
+        f9: 9,
+        f10: 10,
+        f11: 11,
+        f12: 12,
+        f13: 13,
+        f14: -14,
+        f15: -15,
+        f16: -16,
+        f17: -17,
+        f18: false,
+        f19: 19,
+    }
+}
+
+/// Equivalent of the code generated before `serialize_into`, one buffer per field
+fn serialize_per_field(w: &Wide) -> Vec<u8> {
+    let mut v = Vec::new();
+    v.extend_from_slice(&w.f0.serialize());
+    v.extend_from_slice(&w.f1.serialize());
+    v.extend_from_slice(&w.f2.serialize());
+    v.extend_from_slice(&w.f3.serialize());
+    v.extend_from_slice(&w.f4.serialize());
+    v.extend_from_slice(&w.f5.serialize());
+    v.extend_from_slice(&w.f6.serialize());
+    v.extend_from_slice(&w.f7.serialize());
+    v.extend_from_slice(&w.f8.serialize());
+    v.extend_from_slice(&w.f9.serialize());
+    v.extend_from_slice(&w.f10.serialize());
+    v.extend_from_slice(&w.f11.serialize());
+    v.extend_from_slice(&w.f12.serialize());
+    v.extend_from_slice(&w.f13.serialize());
+    v.extend_from_slice(&w.f14.serialize());
+    v.extend_from_slice(&w.f15.serialize());
+    v.extend_from_slice(&w.f16.serialize());
+    v.extend_from_slice(&w.f17.serialize());
+    v.extend_from_slice(&w.f18.serialize());
+    v.extend_from_slice(&w.f19.serialize());
+    v
+}
+
+fn measure<F: FnMut() -> usize>(name: &str, iterations: usize, mut f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut bytes = 0;
+    for _ in 0..iterations {
+        bytes += f();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{}: {:.1} allocations and {:?} per struct ({} bytes)",
+        name,
+        allocations as f64 / iterations as f64,
+        elapsed / iterations as u32,
+        bytes / iterations
+    );
+}
+
+fn main() {
+    let w = wide();
+    assert_eq!(serialize_per_field(&w), w.serialize().to_vec());
+    let iterations = 100_000;
+    measure("per field buffers", iterations, || {
+        serialize_per_field(&w).len()
+    });
+    let mut out = Vec::with_capacity(128);
+    measure("serialize_into", iterations, || {
+        out.clear();
+        w.serialize_into(&mut out);
+        out.len()
+    });
+}
@@ -1,9 +1,182 @@
 extern crate proc_macro;
 use crate::proc_macro::TokenStream;
-use quote::quote;
-use syn;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
 
-#[proc_macro_derive(Deserialize)]
+/// Items of the `#[ensicoin(...)]` attributes
+fn ensicoin_attrs(attrs: &[syn::Attribute]) -> Vec<syn::Meta> {
+    let mut items = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("ensicoin")) {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested.into_iter() {
+                if let syn::NestedMeta::Meta(meta) = nested {
+                    items.push(meta);
+                }
+            }
+        }
+    }
+    items
+}
+
+/// Type of the tag of an enum
+enum TagType {
+    /// Unsigned integer type
+    Int(syn::Ident),
+    /// `VarUint`, the default, which lets enums grow without changing the encoding of existing
+    /// variants
+    VarUint,
+}
+
+impl TagType {
+    /// Statement binding the tag of the variant of index `index` to `tag`
+    fn bind(&self, index: usize) -> proc_macro2::TokenStream {
+        let value = tag_value(index);
+        match self {
+            TagType::Int(t) => quote! { let tag: #t = #value; },
+            TagType::VarUint => {
+                quote! { let tag = ensicoin_serializer::VarUint { value: #value }; }
+            }
+        }
+    }
+
+    /// Expression reading a tag from `de`, as an integer compared to the variant indices
+    fn read(&self) -> proc_macro2::TokenStream {
+        match self {
+            TagType::Int(t) => quote! { <#t>::deserialize(de) },
+            TagType::VarUint => {
+                quote! { ensicoin_serializer::VarUint::deserialize(de).map(|n| n.value) }
+            }
+        }
+    }
+}
+
+/// Type of the tag of an enum: given by `#[ensicoin(tag_type = "...")]` or else a `VarUint`
+fn tag_type(ast: &syn::DeriveInput) -> TagType {
+    for meta in ensicoin_attrs(&ast.attrs) {
+        if let syn::Meta::NameValue(nv) = meta {
+            if nv.path.is_ident("tag_type") {
+                match &nv.lit {
+                    syn::Lit::Str(s) => match s.value().as_str() {
+                        "u8" | "u16" | "u32" => {
+                            return TagType::Int(syn::Ident::new(&s.value(), Span::call_site()))
+                        }
+                        "varuint" => return TagType::VarUint,
+                        t => panic!("Invalid tag_type {} in {}", t, ast.ident),
+                    },
+                    _ => panic!("tag_type must be a string in {}", ast.ident),
+                }
+            }
+        }
+    }
+    TagType::VarUint
+}
+
+/// Item asserting that `field_type` implements `trait_name`, so that the compiler error for a
+/// field that does not points at its type instead of inside the generated code. The check names
+/// `method` through the same `<Type as Trait>` path as the generated methods, so that rustc merges
+/// their errors into this one. It is done in a function with the generics of the type, for field
+/// types using them
+fn assert_field_impl(
+    trait_name: proc_macro2::TokenStream,
+    method: proc_macro2::TokenStream,
+    field_type: &syn::Type,
+    generics: &syn::Generics,
+) -> proc_macro2::TokenStream {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    quote_spanned! {field_type.span()=>
+        const _: fn() = || {
+            #[allow(dead_code)]
+            fn assert_field #impl_generics () #where_clause {
+                let _ = <#field_type as #trait_name>::#method;
+            }
+        };
+    }
+}
+
+/// Whether the struct is in tag-length-value mode, given by `#[ensicoin(tlv)]`
+fn is_tlv(ast: &syn::DeriveInput) -> bool {
+    ensicoin_attrs(&ast.attrs)
+        .iter()
+        .any(|meta| meta.path().is_ident("tlv"))
+}
+
+/// Whether the field is left out of the encoding, given by `#[ensicoin(skip)]`
+fn is_skipped(field: &syn::Field) -> bool {
+    ensicoin_attrs(&field.attrs)
+        .iter()
+        .any(|meta| meta.path().is_ident("skip"))
+}
+
+/// Fields of a struct in tag-length-value mode with their tag, given by `#[ensicoin(tag = N)]`,
+/// skipped fields excepted
+fn field_tags<'a>(
+    name: &syn::Ident,
+    data: &'a syn::DataStruct,
+) -> Vec<(&'a syn::Field, syn::LitInt)> {
+    if let syn::Fields::Unnamed(_) = data.fields {
+        panic!("tlv needs named fields, {} is a tuple struct", name);
+    }
+    let mut tags: Vec<(&syn::Field, syn::LitInt)> = Vec::new();
+    for field in data.fields.iter().filter(|f| !is_skipped(f)) {
+        let tag = ensicoin_attrs(&field.attrs)
+            .into_iter()
+            .find_map(|meta| match meta {
+                syn::Meta::NameValue(nv) if nv.path.is_ident("tag") => match nv.lit {
+                    syn::Lit::Int(tag) => Some(tag),
+                    _ => panic!("tag must be an integer in {}", name),
+                },
+                _ => None,
+            });
+        match tag {
+            Some(tag) => {
+                let value: u64 = tag.base10_parse().unwrap();
+                if tags
+                    .iter()
+                    .any(|(_, t)| t.base10_parse::<u64>().unwrap() == value)
+                {
+                    panic!("Duplicate tag {} in {}", value, name);
+                }
+                tags.push((field, tag));
+            }
+            None => panic!("Missing #[ensicoin(tag = N)] on a field of {}", name),
+        }
+    }
+    tags
+}
+
+/// Local variable holding the value of the field `field_name` in generated code, prefixed so
+/// that it can't clash with the other locals
+fn field_binding(field_name: &syn::Ident) -> syn::Ident {
+    syn::Ident::new(
+        &format!("__field_{}", field_name.unraw()),
+        field_name.span(),
+    )
+}
+
+fn tag_value(index: usize) -> syn::LitInt {
+    syn::LitInt::new(&index.to_string(), Span::call_site())
+}
+
+/// Derives `Deserialize` by reading each field in declaration order, for structs with named
+/// fields and tuple structs.
+///
+/// Enums are read as a tag, the index of the variant, followed by the fields of the variant in
+/// declaration order, for both tuple and struct variants.
+/// The tag is written as a `VarUint`, so that it takes a single byte for up to 253 variants and
+/// adding variants doesn't change the encoding of the existing ones. A fixed size tag can be set
+/// with `#[ensicoin(tag_type = "u8" | "u16" | "u32")]`, `"varuint"` being the default.
+///
+/// Structs marked `#[ensicoin(tlv)]` are instead read as a `VarUint` number of entries, each
+/// being a `VarUint` tag, a `VarUint` length and the field with that tag, given on each field by
+/// `#[ensicoin(tag = N)]`. Entries of unknown tags are skipped and missing fields take their
+/// default value, which allows adding fields without breaking older readers.
+///
+/// Fields marked `#[ensicoin(skip)]` are not part of the encoding and are set to their default
+/// value, so their type must implement `Default`. Skipping is only possible on named fields, as
+/// the fields after a skipped one in a tuple struct would be read at the wrong position.
+#[proc_macro_derive(Deserialize, attributes(ensicoin))]
 pub fn deserialize_macro_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
 
@@ -13,55 +186,305 @@ pub fn deserialize_macro_derive(input: TokenStream) -> TokenStream {
 fn impl_deserialize_macro(ast: &syn::DeriveInput) -> TokenStream {
     let name = &ast.ident;
     let generics = &ast.generics;
+    // Items asserting that each field implements the trait it needs
+    let mut assertions = quote! {};
 
     let mut field_list = quote! {};
     let mut body = quote! {};
 
     match &ast.data {
-        syn::Data::Struct(data) => {
-            for field in data.fields.iter() {
+        syn::Data::Struct(data) if is_tlv(ast) => {
+            let tags = field_tags(name, data);
+            let mut arms = quote! {};
+            for field in data.fields.iter().filter(|f| is_skipped(f)) {
+                let field_name = &field.ident;
+                let assertion =
+                    assert_field_impl(quote! {Default}, quote! {default}, &field.ty, generics);
+                assertions = quote! {#assertions #assertion};
+                field_list = quote! {#field_list
+                #field_name: Default::default(),};
+            }
+            for (field, tag) in tags.iter() {
                 let field_type = &field.ty;
-                match &field.ident {
-                    Some(field_name) => {
-                        body = quote! {
-                            #body
-                            let #field_name = match <#field_type>::deserialize(de) {
-                                Ok(v) => v,
+                let field_name = field.ident.as_ref().unwrap();
+                let binding = field_binding(field_name);
+                let assertion = assert_field_impl(
+                    quote! {Deserialize},
+                    quote! {deserialize},
+                    field_type,
+                    generics,
+                );
+                assertions = quote! {#assertions #assertion};
+                body = quote! {
+                    #body
+                    let mut #binding: Option<#field_type> = None;
+                };
+                arms = quote! {
+                    #arms
+                    #tag => {
+                        if #binding.is_some() {
+                            return Err(ensicoin_serializer::Error::Message(format!(
+                                        "Duplicate tag {} in {}",
+                                        __tag,
+                                        stringify!(#name)
+                            )));
+                        }
+                        #binding = match de.deserialize_sized_lenient::<#field_type>() {
+                            Ok(v) => Some(v),
+                            Err(e) => {
+                                return Err(ensicoin_serializer::Error::Message(format!(
+                                            "Error in reading {} {}: {}",
+                                            stringify!(#name),
+                                            stringify!(#field_name),
+                                            e
+                                )));
+                            }
+                        };
+                    }
+                };
+                field_list = quote! {#field_list
+                #field_name: #binding.unwrap_or_default(),};
+            }
+            body = quote! {
+                let __count = match ensicoin_serializer::VarUint::deserialize(de) {
+                    Ok(v) => v.value,
+                    Err(e) => {
+                        return Err(ensicoin_serializer::Error::Message(format!(
+                                    "Error in reading {} entry count: {}",
+                                    stringify!(#name),
+                                    e
+                        )));
+                    }
+                };
+                #body
+                for _ in 0..__count {
+                    let __tag = match ensicoin_serializer::VarUint::deserialize(de) {
+                        Ok(v) => v.value,
+                        Err(e) => {
+                            return Err(ensicoin_serializer::Error::Message(format!(
+                                        "Error in reading {} tag: {}",
+                                        stringify!(#name),
+                                        e
+                            )));
+                        }
+                    };
+                    match __tag {
+                        #arms
+                        _ => {
+                            let __length = match ensicoin_serializer::VarUint::deserialize(de) {
+                                Ok(v) => v.value,
                                 Err(e) => {
                                     return Err(ensicoin_serializer::Error::Message(format!(
-                                                "Error in reading {} {}: {}",
+                                                "Error in reading {} length of tag {}: {}",
                                                 stringify!(#name),
-                                                stringify!(#field_name),
+                                                __tag,
                                                 e
                                     )));
                                 }
                             };
-                        };
-                        field_list = quote! {#field_list
-                        #field_name,};
+                            de.extract_bytes(__length as usize)?;
+                        }
                     }
-                    None => panic!("Can't derive unamed field in {}", name),
                 }
+                Ok(#name {#field_list
+                })
+            };
+        }
+        syn::Data::Struct(data) => {
+            for (i, field) in data.fields.iter().enumerate() {
+                let field_type = &field.ty;
+                // Fields are bound to `__field_name`, or `__field_0`, `__field_1`... for tuple
+                // structs, so that they can't shadow `de`
+                let (binding, field_label) = match &field.ident {
+                    Some(field_name) => {
+                        (field_binding(field_name), quote! {stringify!(#field_name)})
+                    }
+                    None => (
+                        syn::Ident::new(&format!("__field_{}", i), Span::call_site()),
+                        quote! {#i},
+                    ),
+                };
+                let field_value = match &field.ident {
+                    Some(field_name) => quote! {#field_name: #binding},
+                    None => quote! {#binding},
+                };
+                if is_skipped(field) {
+                    if field.ident.is_none() {
+                        panic!(
+                            "skip can't be used on field {} of tuple struct {}, as the fields \
+                             after it would change position",
+                            i, name
+                        );
+                    }
+                    let assertion =
+                        assert_field_impl(quote! {Default}, quote! {default}, field_type, generics);
+                    assertions = quote! {#assertions #assertion};
+                    body = quote! {
+                        #body
+                        let #binding = <#field_type as Default>::default();
+                    };
+                    field_list = quote! {#field_list
+                    #field_value,};
+                    continue;
+                }
+                let assertion = assert_field_impl(
+                    quote! {Deserialize},
+                    quote! {deserialize},
+                    field_type,
+                    generics,
+                );
+                assertions = quote! {#assertions #assertion};
+                body = quote! {
+                    #body
+                    let #binding = match <#field_type as Deserialize>::deserialize(de) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            return Err(ensicoin_serializer::Error::Message(format!(
+                                        "Error in reading {} {}: {}",
+                                        stringify!(#name),
+                                        #field_label,
+                                        e
+                            )));
+                        }
+                    };
+                };
+                field_list = quote! {#field_list
+                #field_value,};
             }
+            body = match &data.fields {
+                syn::Fields::Unnamed(_) => quote! {
+                    #body
+                    Ok(#name(#field_list))
+                },
+                _ => quote! {
+                    #body
+                    Ok(#name {#field_list
+                    })
+                },
+            };
         }
-        _ => panic!("Can only derive struts, {} is invalid", name),
+        syn::Data::Enum(data) => {
+            let read_tag = tag_type(ast).read();
+            let mut arms = quote! {};
+            for (index, variant) in data.variants.iter().enumerate() {
+                let variant_name = &variant.ident;
+                let tag = tag_value(index);
+                let mut variant_body = quote! {};
+                let mut variant_fields = quote! {};
+                match &variant.fields {
+                    syn::Fields::Unit => (),
+                    syn::Fields::Unnamed(fields) => {
+                        for (i, field) in fields.unnamed.iter().enumerate() {
+                            let field_type = &field.ty;
+                            let binding =
+                                syn::Ident::new(&format!("__field_{}", i), Span::call_site());
+                            let assertion = assert_field_impl(
+                                quote! {Deserialize},
+                                quote! {deserialize},
+                                field_type,
+                                generics,
+                            );
+                            assertions = quote! {#assertions #assertion};
+                            variant_body = quote! {
+                                #variant_body
+                                let #binding = match <#field_type as Deserialize>::deserialize(de) {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        return Err(ensicoin_serializer::Error::Message(format!(
+                                                    "Error in reading {}::{} {}: {}",
+                                                    stringify!(#name),
+                                                    stringify!(#variant_name),
+                                                    #i,
+                                                    e
+                                        )));
+                                    }
+                                };
+                            };
+                            variant_fields = quote! {#variant_fields #binding,};
+                        }
+                        variant_fields = quote! {(#variant_fields)};
+                    }
+                    syn::Fields::Named(fields) => {
+                        for field in fields.named.iter() {
+                            let field_type = &field.ty;
+                            let field_name = field.ident.as_ref().unwrap();
+                            let binding = field_binding(field_name);
+                            let assertion = assert_field_impl(
+                                quote! {Deserialize},
+                                quote! {deserialize},
+                                field_type,
+                                generics,
+                            );
+                            assertions = quote! {#assertions #assertion};
+                            variant_body = quote! {
+                                #variant_body
+                                let #binding = match <#field_type as Deserialize>::deserialize(de) {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        return Err(ensicoin_serializer::Error::Message(format!(
+                                                    "Error in reading {}::{} {}: {}",
+                                                    stringify!(#name),
+                                                    stringify!(#variant_name),
+                                                    stringify!(#field_name),
+                                                    e
+                                        )));
+                                    }
+                                };
+                            };
+                            variant_fields = quote! {#variant_fields #field_name: #binding,};
+                        }
+                        variant_fields = quote! {{#variant_fields}};
+                    }
+                }
+                arms = quote! {
+                    #arms
+                    #tag => {
+                        #variant_body
+                        Ok(#name::#variant_name #variant_fields)
+                    }
+                };
+            }
+            body = quote! {
+                let tag = match #read_tag {
+                    Ok(v) => v,
+                    Err(e) => {
+                        return Err(ensicoin_serializer::Error::Message(format!(
+                                    "Error in reading {} tag: {}",
+                                    stringify!(#name),
+                                    e
+                        )));
+                    }
+                };
+                match tag {
+                    #arms
+                    _ => Err(ensicoin_serializer::Error::Message(format!(
+                                "Unknown tag {} for {}",
+                                tag,
+                                stringify!(#name)
+                    ))),
+                }
+            };
+        }
+        _ => panic!("Can only derive structs and enums, {} is invalid", name),
     };
 
     let gen = quote! {
+        #assertions
+
         impl #generics Deserialize for #name #generics {
             fn deserialize(
                 de: &mut ensicoin_serializer::Deserializer,
             ) -> ensicoin_serializer::Result<Self> {
                 #body
-                Ok(#name {#field_list
-                })
             }
        }
     };
     gen.into()
 }
 
-#[proc_macro_derive(Serialize)]
+/// Derives `Serialize` by writing each field in declaration order, see the `Deserialize` derive
+/// for the encoding of enums
+#[proc_macro_derive(Serialize, attributes(ensicoin))]
 pub fn serialize_macro_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
 
@@ -71,6 +494,300 @@ pub fn serialize_macro_derive(input: TokenStream) -> TokenStream {
 fn impl_serialize_macro(ast: &syn::DeriveInput) -> TokenStream {
     let name = &ast.ident;
     let generics = &ast.generics;
+    // Items asserting that each field implements the trait it needs
+    let mut assertions = quote! {};
+
+    let mut body = quote! {};
+    // Statements adding the serialized size of each part to `size`
+    let mut size = quote! {};
+    // Statements writing each part at `offset` in `buf`
+    let mut slice = quote! {};
+    match &ast.data {
+        syn::Data::Struct(data) if is_tlv(ast) => {
+            let tags = field_tags(name, data);
+            let count = tags.len() as u64;
+            body = quote! {
+                Serialize::serialize_into(&ensicoin_serializer::VarUint { value: #count }, v);
+            };
+            size = quote! {
+                size += Serialize::serialized_size(&ensicoin_serializer::VarUint { value: #count });
+            };
+            slice = write_slice(
+                quote! {Serialize},
+                quote! {&ensicoin_serializer::VarUint { value: #count }},
+            );
+            for (field, tag) in tags.iter() {
+                let field_name = &field.ident;
+                let field_type = &field.ty;
+                let assertion =
+                    assert_field_impl(quote! {Serialize}, quote! {serialize}, field_type, generics);
+                assertions = quote! {#assertions #assertion};
+                body = quote! {
+                    #body
+                    let field = <#field_type as Serialize>::serialize(&self.#field_name);
+                    Serialize::serialize_into(&ensicoin_serializer::VarUint { value: #tag }, v);
+                    Serialize::serialize_into(
+                        &ensicoin_serializer::VarUint { value: field.len() as u64 },
+                        v,
+                    );
+                    v.extend_from_slice(&field);
+                };
+                size = quote! {
+                    #size
+                    let field = <#field_type as Serialize>::serialized_size(&self.#field_name);
+                    size += Serialize::serialized_size(&ensicoin_serializer::VarUint { value: #tag });
+                    size += Serialize::serialized_size(
+                        &ensicoin_serializer::VarUint { value: field as u64 },
+                    );
+                    size += field;
+                };
+                let write_tag = write_slice(
+                    quote! {Serialize},
+                    quote! {&ensicoin_serializer::VarUint { value: #tag }},
+                );
+                let write_length = write_slice(
+                    quote! {Serialize},
+                    quote! {&ensicoin_serializer::VarUint { value: field as u64 }},
+                );
+                let write_field = write_slice(
+                    quote! {<#field_type as Serialize>},
+                    quote! {&self.#field_name},
+                );
+                slice = quote! {
+                    #slice
+                    let field = <#field_type as Serialize>::serialized_size(&self.#field_name);
+                    #write_tag
+                    #write_length
+                    #write_field
+                };
+            }
+        }
+        syn::Data::Struct(data) => {
+            for (i, field) in data.fields.iter().enumerate() {
+                if is_skipped(field) {
+                    if field.ident.is_none() {
+                        panic!("skip can't be used on field {} of tuple struct {}", i, name);
+                    }
+                    continue;
+                }
+                // `self.name` for named fields, `self.0`, `self.1`... for tuple structs
+                let field_name = match &field.ident {
+                    Some(field_name) => quote! {#field_name},
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote! {#index}
+                    }
+                };
+                let field_type = &field.ty;
+                let assertion =
+                    assert_field_impl(quote! {Serialize}, quote! {serialize}, field_type, generics);
+                assertions = quote! {#assertions #assertion};
+                body = quote! {
+                    #body
+                    <#field_type as Serialize>::serialize_into(&self.#field_name, v);
+                };
+                size = quote! {
+                    #size
+                    size += <#field_type as Serialize>::serialized_size(&self.#field_name);
+                };
+                let write_field = write_slice(
+                    quote! {<#field_type as Serialize>},
+                    quote! {&self.#field_name},
+                );
+                slice = quote! {
+                    #slice
+                    #write_field
+                };
+            }
+        }
+        syn::Data::Enum(data) => {
+            let tag_type = tag_type(ast);
+            let mut arms = quote! {};
+            let mut size_arms = quote! {};
+            let mut slice_arms = quote! {};
+            for (index, variant) in data.variants.iter().enumerate() {
+                let variant_name = &variant.ident;
+                let bind_tag = tag_type.bind(index);
+                let mut variant_body = quote! {
+                    #bind_tag
+                    Serialize::serialize_into(&tag, v);
+                };
+                let mut variant_size = quote! {
+                    #bind_tag
+                    size += Serialize::serialized_size(&tag);
+                };
+                let write_tag = write_slice(quote! {Serialize}, quote! {&tag});
+                let mut variant_slice = quote! {
+                    #bind_tag
+                    #write_tag
+                };
+                let mut variant_fields = quote! {};
+                match &variant.fields {
+                    syn::Fields::Unit => (),
+                    syn::Fields::Unnamed(fields) => {
+                        for (i, field) in fields.unnamed.iter().enumerate() {
+                            let binding =
+                                syn::Ident::new(&format!("__field_{}", i), Span::call_site());
+                            let field_type = &field.ty;
+                            let assertion = assert_field_impl(
+                                quote! {Serialize},
+                                quote! {serialize},
+                                field_type,
+                                generics,
+                            );
+                            assertions = quote! {#assertions #assertion};
+                            variant_body = quote! {
+                                #variant_body
+                                <#field_type as Serialize>::serialize_into(#binding, v);
+                            };
+                            variant_size = quote! {
+                                #variant_size
+                                size += <#field_type as Serialize>::serialized_size(#binding);
+                            };
+                            let write_field =
+                                write_slice(quote! {<#field_type as Serialize>}, quote! {#binding});
+                            variant_slice = quote! {
+                                #variant_slice
+                                #write_field
+                            };
+                            variant_fields = quote! {#variant_fields #binding,};
+                        }
+                        variant_fields = quote! {(#variant_fields)};
+                    }
+                    syn::Fields::Named(fields) => {
+                        for field in fields.named.iter() {
+                            let field_name = field.ident.as_ref().unwrap();
+                            let binding = field_binding(field_name);
+                            let field_type = &field.ty;
+                            let assertion = assert_field_impl(
+                                quote! {Serialize},
+                                quote! {serialize},
+                                field_type,
+                                generics,
+                            );
+                            assertions = quote! {#assertions #assertion};
+                            variant_body = quote! {
+                                #variant_body
+                                <#field_type as Serialize>::serialize_into(#binding, v);
+                            };
+                            variant_size = quote! {
+                                #variant_size
+                                size += <#field_type as Serialize>::serialized_size(#binding);
+                            };
+                            let write_field =
+                                write_slice(quote! {<#field_type as Serialize>}, quote! {#binding});
+                            variant_slice = quote! {
+                                #variant_slice
+                                #write_field
+                            };
+                            variant_fields = quote! {#variant_fields #field_name: #binding,};
+                        }
+                        variant_fields = quote! {{#variant_fields}};
+                    }
+                }
+                arms = quote! {
+                    #arms
+                    #name::#variant_name #variant_fields => {
+                        #variant_body
+                    }
+                };
+                size_arms = quote! {
+                    #size_arms
+                    #name::#variant_name #variant_fields => {
+                        #variant_size
+                    }
+                };
+                slice_arms = quote! {
+                    #slice_arms
+                    #name::#variant_name #variant_fields => {
+                        #variant_slice
+                    }
+                };
+            }
+            body = quote! {
+                match self {
+                    #arms
+                }
+            };
+            size = quote! {
+                match self {
+                    #size_arms
+                }
+            };
+            slice = quote! {
+                match self {
+                    #slice_arms
+                }
+            };
+        }
+        _ => panic!("Can only derive structs and enums, {} is invalid", name),
+    }
+
+    let gen = quote! {
+        #assertions
+
+        impl #generics Serialize for #name #generics {
+            fn serialize(&self) -> bytes::Bytes {
+                let mut v = Vec::new();
+                Serialize::serialize_into(self, &mut v);
+                bytes::Bytes::from(v)
+            }
+
+            fn serialize_into(&self, v: &mut Vec<u8>) {
+                #body
+            }
+
+            fn serialized_size(&self) -> usize {
+                let mut size = 0;
+                #size
+                size
+            }
+
+            fn serialize_into_slice(
+                &self,
+                buf: &mut [u8],
+            ) -> ensicoin_serializer::Result<usize> {
+                let size = Serialize::serialized_size(self);
+                if size > buf.len() {
+                    return Err(ensicoin_serializer::Error::LengthExceeded(
+                        size as u64,
+                        buf.len() as u64,
+                    ));
+                }
+                let mut offset = 0;
+                #slice
+                Ok(offset)
+            }
+       }
+    };
+    gen.into()
+}
+
+/// Statement writing `value` at `offset` in `buf` through `serializer`, `Serialize` or a
+/// qualified path to its impl, and moving `offset` past it
+fn write_slice(
+    serializer: proc_macro2::TokenStream,
+    value: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        offset += #serializer::serialize_into_slice(#value, &mut buf[offset..])?;
+    }
+}
+
+/// Derives `Describe`, rendering a struct as a JSON object of its fields. Needs the `describe`
+/// feature of `ensicoin_serializer`
+#[proc_macro_derive(Describe)]
+pub fn describe_macro_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+
+    impl_describe_macro(&ast)
+}
+
+fn impl_describe_macro(ast: &syn::DeriveInput) -> TokenStream {
+    let name = &ast.ident;
+    let generics = &ast.generics;
+    // Items asserting that each field implements the trait it needs
+    let mut assertions = quote! {};
 
     let mut body = quote! {};
     match &ast.data {
@@ -78,24 +795,39 @@ fn impl_serialize_macro(ast: &syn::DeriveInput) -> TokenStream {
             for field in data.fields.iter() {
                 match &field.ident {
                     Some(field_name) => {
+                        let field_type = &field.ty;
+                        let assertion = assert_field_impl(
+                            quote! {ensicoin_serializer::describe::Describe},
+                            quote! {to_json_value},
+                            field_type,
+                            generics,
+                        );
+                        assertions = quote! {#assertions #assertion};
                         body = quote! {
                             #body
-                            v.extend_from_slice(&self.#field_name.serialize());
+                            map.insert(
+                                String::from(stringify!(#field_name)),
+                                <#field_type as ensicoin_serializer::describe::Describe>::to_json_value(
+                                    &self.#field_name,
+                                ),
+                            );
                         }
                     }
                     None => panic!("Can't derive unamed field in {}", name),
                 }
             }
         }
-        _ => panic!("Can only derive structs, {} is invalid", name),
+        _ => panic!("Can only derive Describe for structs, {} is invalid", name),
     }
 
     let gen = quote! {
-        impl #generics Serialize for #name #generics {
-            fn serialize(&self) -> bytes::Bytes {
-                let mut v = bytes::Bytes::new();
+        #assertions
+
+        impl #generics ensicoin_serializer::describe::Describe for #name #generics {
+            fn to_json_value(&self) -> ensicoin_serializer::describe::serde_json::Value {
+                let mut map = ensicoin_serializer::describe::serde_json::Map::new();
                 #body
-                v
+                ensicoin_serializer::describe::serde_json::Value::Object(map)
             }
        }
     };
@@ -0,0 +1,35 @@
+extern crate ensicoin_serializer;
+
+use ensicoin_serializer::describe::serde_json::json;
+use ensicoin_serializer::describe::Describe;
+use ensicoin_serializer::Sha256Result;
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+#[derive(Describe)]
+pub struct Announce {
+    pub hash: Sha256Result,
+    pub payload: Vec<u8>,
+    pub height: u32,
+    pub names: Vec<String>,
+}
+
+#[test]
+fn describe_struct() {
+    let announce = Announce {
+        hash: Sha256Result::from([0xAB; 32]),
+        payload: vec![0xDE, 0xAD],
+        height: 7,
+        names: vec![String::from("a")],
+    };
+    assert_eq!(
+        json!({
+            "hash": "abababababababababababababababababababababababababababababababab",
+            "payload": "dead",
+            "height": 7,
+            "names": ["a"],
+        }),
+        announce.to_json_value()
+    );
+}
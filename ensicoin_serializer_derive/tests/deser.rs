@@ -24,3 +24,132 @@ fn both_works() {
     let new_s = SomeStruct::deserialize(&mut de);
     assert_eq!(new_s.unwrap(), s);
 }
+
+#[test]
+fn field_error_names_field() {
+    let raw = vec![3, 2, 0, 0, 0, 0, 0, 0, 0, 1];
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(raw));
+    let error = SomeStruct::deserialize(&mut de).unwrap_err().to_string();
+    assert!(error.contains("SomeStruct gen_some"), "{}", error);
+}
+
+#[derive(Serialize)]
+pub struct Nested {
+    pub inner: SomeStruct,
+    pub name: String,
+    pub offset: i32,
+    pub parent: Option<u16>,
+}
+
+#[test]
+fn serialize_into_matches_serialize() {
+    let s = Nested {
+        inner: SomeStruct {
+            thing: 3,
+            gen_some: vec![1, 9],
+        },
+        name: "nested".to_string(),
+        offset: -2,
+        parent: Some(300),
+    };
+    let mut v = vec![0xAA];
+    s.serialize_into(&mut v);
+    assert_eq!(0xAA, v[0]);
+    assert_eq!(s.serialize().to_vec(), v[1..].to_vec());
+}
+
+#[test]
+fn serialized_size_matches_serialize() {
+    let s = Nested {
+        inner: SomeStruct {
+            thing: 3,
+            gen_some: vec![1; 300],
+        },
+        name: "nested".to_string(),
+        offset: -2,
+        parent: None,
+    };
+    assert_eq!(s.serialize().len(), s.serialized_size());
+    assert_eq!(s.inner.serialize().len(), s.inner.serialized_size());
+}
+
+#[test]
+fn serialize_into_slice_matches_serialize() {
+    let s = Nested {
+        inner: SomeStruct {
+            thing: 3,
+            gen_some: vec![1, 9],
+        },
+        name: "nested".to_string(),
+        offset: -2,
+        parent: Some(300),
+    };
+    let expected = s.serialize().to_vec();
+    let mut buf = [0; 64];
+    assert_eq!(expected.len(), s.serialize_into_slice(&mut buf).unwrap());
+    assert_eq!(expected, buf[..expected.len()].to_vec());
+    let mut small = vec![0; expected.len() - 1];
+    assert!(s.serialize_into_slice(&mut small).is_err());
+    assert_eq!(vec![0; expected.len() - 1], small);
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct Hash(pub ensicoin_serializer::Sha256Result);
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct Pair(pub u16, pub String);
+
+#[test]
+fn tuple_structs() {
+    let hash = Hash(ensicoin_serializer::Sha256Result::from([3; 32]));
+    let raw = hash.serialize();
+    assert_eq!(vec![3; 32], raw.to_vec());
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(raw));
+    assert_eq!(hash, Hash::deserialize(&mut de).unwrap());
+
+    let pair = Pair(258, "ab".to_string());
+    let raw = pair.serialize();
+    assert_eq!(vec![1, 2, 2, b'a', b'b'], raw.to_vec());
+    assert_eq!(raw.len(), pair.serialized_size());
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(raw));
+    assert_eq!(pair, Pair::deserialize(&mut de).unwrap());
+
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(vec![1, 2, 5]));
+    let error = Pair::deserialize(&mut de).unwrap_err().to_string();
+    assert!(error.contains("Pair 1"), "{}", error);
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct Cached {
+    pub value: u32,
+    #[ensicoin(skip)]
+    pub cache: Option<String>,
+}
+
+#[test]
+fn skipped_field() {
+    let cached = Cached {
+        value: 7,
+        cache: Some("seven".to_string()),
+    };
+    let raw = cached.serialize();
+    assert_eq!(vec![0, 0, 0, 7], raw.to_vec());
+    assert_eq!(4, cached.serialized_size());
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(raw));
+    let decoded = Cached::deserialize(&mut de).unwrap();
+    assert_eq!(7, decoded.value);
+    assert_eq!(None, decoded.cache);
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct Shadowing {
+    pub de: u32,
+    pub other: u32,
+}
+
+#[test]
+fn field_named_like_deserializer() {
+    let s = Shadowing { de: 1, other: 2 };
+    let mut de = ensicoin_serializer::Deserializer::new(bytes::BytesMut::from(s.serialize()));
+    assert_eq!(s, Shadowing::deserialize(&mut de).unwrap());
+}
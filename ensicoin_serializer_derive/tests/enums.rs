@@ -0,0 +1,445 @@
+extern crate bytes;
+extern crate ensicoin_serializer;
+
+use ensicoin_serializer::Deserialize;
+use ensicoin_serializer::Deserializer;
+use ensicoin_serializer::Serialize;
+
+use bytes::BytesMut;
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum Small {
+    Empty,
+    Pair(u8, String),
+    Single(u32),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum Shapes {
+    A { x: u32, y: String },
+    B(u8),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[ensicoin(tag_type = "u32")]
+pub enum Wide {
+    A,
+    B(u8),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum Command {
+    Stop,
+    Move { x: i32, y: i32 },
+    Say(String),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum Reject {
+    Message { tag: String, v: u32 },
+    Sized { size: u8, de: u8 },
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub enum Large {
+    V0,
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+    V9,
+    V10,
+    V11,
+    V12,
+    V13,
+    V14,
+    V15,
+    V16,
+    V17,
+    V18,
+    V19,
+    V20,
+    V21,
+    V22,
+    V23,
+    V24,
+    V25,
+    V26,
+    V27,
+    V28,
+    V29,
+    V30,
+    V31,
+    V32,
+    V33,
+    V34,
+    V35,
+    V36,
+    V37,
+    V38,
+    V39,
+    V40,
+    V41,
+    V42,
+    V43,
+    V44,
+    V45,
+    V46,
+    V47,
+    V48,
+    V49,
+    V50,
+    V51,
+    V52,
+    V53,
+    V54,
+    V55,
+    V56,
+    V57,
+    V58,
+    V59,
+    V60,
+    V61,
+    V62,
+    V63,
+    V64,
+    V65,
+    V66,
+    V67,
+    V68,
+    V69,
+    V70,
+    V71,
+    V72,
+    V73,
+    V74,
+    V75,
+    V76,
+    V77,
+    V78,
+    V79,
+    V80,
+    V81,
+    V82,
+    V83,
+    V84,
+    V85,
+    V86,
+    V87,
+    V88,
+    V89,
+    V90,
+    V91,
+    V92,
+    V93,
+    V94,
+    V95,
+    V96,
+    V97,
+    V98,
+    V99,
+    V100,
+    V101,
+    V102,
+    V103,
+    V104,
+    V105,
+    V106,
+    V107,
+    V108,
+    V109,
+    V110,
+    V111,
+    V112,
+    V113,
+    V114,
+    V115,
+    V116,
+    V117,
+    V118,
+    V119,
+    V120,
+    V121,
+    V122,
+    V123,
+    V124,
+    V125,
+    V126,
+    V127,
+    V128,
+    V129,
+    V130,
+    V131,
+    V132,
+    V133,
+    V134,
+    V135,
+    V136,
+    V137,
+    V138,
+    V139,
+    V140,
+    V141,
+    V142,
+    V143,
+    V144,
+    V145,
+    V146,
+    V147,
+    V148,
+    V149,
+    V150,
+    V151,
+    V152,
+    V153,
+    V154,
+    V155,
+    V156,
+    V157,
+    V158,
+    V159,
+    V160,
+    V161,
+    V162,
+    V163,
+    V164,
+    V165,
+    V166,
+    V167,
+    V168,
+    V169,
+    V170,
+    V171,
+    V172,
+    V173,
+    V174,
+    V175,
+    V176,
+    V177,
+    V178,
+    V179,
+    V180,
+    V181,
+    V182,
+    V183,
+    V184,
+    V185,
+    V186,
+    V187,
+    V188,
+    V189,
+    V190,
+    V191,
+    V192,
+    V193,
+    V194,
+    V195,
+    V196,
+    V197,
+    V198,
+    V199,
+    V200,
+    V201,
+    V202,
+    V203,
+    V204,
+    V205,
+    V206,
+    V207,
+    V208,
+    V209,
+    V210,
+    V211,
+    V212,
+    V213,
+    V214,
+    V215,
+    V216,
+    V217,
+    V218,
+    V219,
+    V220,
+    V221,
+    V222,
+    V223,
+    V224,
+    V225,
+    V226,
+    V227,
+    V228,
+    V229,
+    V230,
+    V231,
+    V232,
+    V233,
+    V234,
+    V235,
+    V236,
+    V237,
+    V238,
+    V239,
+    V240,
+    V241,
+    V242,
+    V243,
+    V244,
+    V245,
+    V246,
+    V247,
+    V248,
+    V249,
+    V250,
+    V251,
+    V252,
+    V253,
+    V254,
+    V255,
+    V256,
+    V257,
+    V258,
+    V259,
+    V260,
+    V261,
+    V262,
+    V263,
+    V264,
+    V265,
+    V266,
+    V267,
+    V268,
+    V269,
+    V270,
+    V271,
+    V272,
+    V273,
+    V274,
+    V275,
+    V276,
+    V277,
+    V278,
+    V279,
+    V280,
+    V281,
+    V282,
+    V283,
+    V284,
+    V285,
+    V286,
+    V287,
+    V288,
+    V289,
+    V290,
+    V291,
+    V292,
+    V293,
+    V294,
+    V295,
+    V296,
+    V297,
+    V298,
+    V299,
+}
+
+fn round_trip<T: Serialize + Deserialize>(value: &T) -> T {
+    assert_eq!(value.serialize().len(), value.serialized_size());
+    let mut de = Deserializer::new(BytesMut::from(value.serialize().to_vec()));
+    T::deserialize(&mut de).unwrap()
+}
+
+#[test]
+fn small_enum() {
+    let pair = Small::Pair(7, String::from("a"));
+    assert_eq!(vec![1, 7, 1, b'a'], pair.serialize());
+    assert_eq!(pair, round_trip(&pair));
+    assert_eq!(vec![0], Small::Empty.serialize());
+    assert_eq!(Small::Empty, round_trip(&Small::Empty));
+    assert_eq!(Small::Single(3), round_trip(&Small::Single(3)));
+}
+
+#[test]
+fn unknown_tag() {
+    let mut de = Deserializer::new(BytesMut::from(vec![3]));
+    assert!(Small::deserialize(&mut de).is_err());
+}
+
+#[test]
+fn large_enum() {
+    assert_eq!(vec![0], Large::V0.serialize());
+    assert_eq!(vec![252], Large::V252.serialize());
+    assert_eq!(vec![0xFD, 0, 253], Large::V253.serialize());
+    assert_eq!(vec![0xFD, 1, 43], Large::V299.serialize());
+    assert_eq!(Large::V299, round_trip(&Large::V299));
+}
+
+#[test]
+fn tag_type_override() {
+    assert_eq!(vec![0, 0, 0, 1, 9], Wide::B(9).serialize());
+    assert_eq!(Wide::B(9), round_trip(&Wide::B(9)));
+}
+
+#[test]
+fn struct_variant() {
+    let a = Shapes::A {
+        x: 258,
+        y: String::from("b"),
+    };
+    assert_eq!(vec![0, 0, 0, 1, 2, 1, b'b'], a.serialize());
+    assert_eq!(a, round_trip(&a));
+    assert_eq!(vec![1, 5], Shapes::B(5).serialize());
+    assert_eq!(Shapes::B(5), round_trip(&Shapes::B(5)));
+}
+
+#[test]
+fn varuint_tag() {
+    let command = Command::Move { x: -1, y: 2 };
+    assert_eq!(
+        vec![1, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 2],
+        command.serialize()
+    );
+    assert_eq!(command, round_trip(&command));
+    assert_eq!(vec![0], Command::Stop.serialize());
+    assert_eq!(Command::Stop, round_trip(&Command::Stop));
+    let say = Command::Say(String::from("hi"));
+    assert_eq!(say, round_trip(&say));
+
+    let mut de = Deserializer::new(BytesMut::from(vec![3]));
+    assert!(Command::deserialize(&mut de).is_err());
+    let mut de = Deserializer::new(BytesMut::from(vec![0xFD, 1, 0]));
+    let error = Command::deserialize(&mut de).unwrap_err().to_string();
+    assert!(error.contains("Unknown tag 256"), "{}", error);
+}
+
+#[test]
+fn fields_named_like_locals() {
+    let message = Reject::Message {
+        tag: String::from("a"),
+        v: 3,
+    };
+    assert_eq!(vec![0, 1, b'a', 0, 0, 0, 3], message.serialize());
+    assert_eq!(7, message.serialized_size());
+    assert_eq!(message, round_trip(&message));
+    let sized = Reject::Sized { size: 1, de: 2 };
+    assert_eq!(vec![1, 1, 2], sized.serialize());
+    assert_eq!(sized, round_trip(&sized));
+}
+
+#[test]
+fn serialize_into_slice_matches_serialize() {
+    let values = [
+        Command::Stop,
+        Command::Move { x: -1, y: 2 },
+        Command::Say(String::from("hi")),
+    ];
+    for value in values.iter() {
+        let mut buf = [0; 16];
+        let size = value.serialize_into_slice(&mut buf).unwrap();
+        assert_eq!(value.serialize().to_vec(), buf[..size].to_vec());
+    }
+}
@@ -0,0 +1,36 @@
+extern crate bytes;
+extern crate ensicoin_serializer;
+
+use ensicoin_serializer::Deserialize;
+use ensicoin_serializer::Message;
+use ensicoin_serializer::Serialize;
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+pub struct Ping {
+    pub nonce: u64,
+    pub tag: String,
+}
+
+#[test]
+fn encode_decode() {
+    let ping = Ping {
+        nonce: 42,
+        tag: String::from("hi"),
+    };
+    let raw = ping.encode();
+    assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 42, 2, 104, 105], raw);
+    assert_eq!(Ping::decode(raw).unwrap(), ping);
+}
+
+#[test]
+fn encoded_len() {
+    let ping = Ping {
+        nonce: 1,
+        tag: String::from("abc"),
+    };
+    assert_eq!(12, ping.encoded_len());
+    assert_eq!(ping.encode().len(), ping.encoded_len());
+}
@@ -0,0 +1,104 @@
+extern crate bytes;
+extern crate ensicoin_serializer;
+
+use ensicoin_serializer::Deserialize;
+use ensicoin_serializer::Deserializer;
+use ensicoin_serializer::Serialize;
+
+use bytes::BytesMut;
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[ensicoin(tlv)]
+pub struct Record {
+    #[ensicoin(tag = 1)]
+    id: u32,
+    #[ensicoin(tag = 3)]
+    name: String,
+}
+
+#[test]
+fn tlv_round_trip() {
+    let record = Record {
+        id: 258,
+        name: String::from("ab"),
+    };
+    let v = record.serialize();
+    assert_eq!(vec![2, 1, 4, 0, 0, 1, 2, 3, 3, 2, b'a', b'b'], v.to_vec());
+    assert_eq!(v.len(), record.serialized_size());
+    let mut buf = [0; 12];
+    assert_eq!(12, record.serialize_into_slice(&mut buf).unwrap());
+    assert_eq!(v.to_vec(), buf.to_vec());
+    let mut de = Deserializer::new(BytesMut::from(v));
+    assert_eq!(record, Record::deserialize(&mut de).unwrap());
+}
+
+#[test]
+fn tlv_unknown_tag() {
+    // Tag 2 is unknown, tag 3 is missing and defaults to an empty name
+    let v = vec![2, 2, 3, 7, 7, 7, 1, 4, 0, 0, 0, 9, 0xAA];
+    let mut de = Deserializer::new(BytesMut::from(v));
+    assert_eq!(
+        Record {
+            id: 9,
+            name: String::new(),
+        },
+        Record::deserialize(&mut de).unwrap()
+    );
+    assert_eq!(0xAA, u8::deserialize(&mut de).unwrap());
+}
+
+#[test]
+fn tlv_duplicate_tag() {
+    let v = vec![2, 1, 4, 0, 0, 0, 9, 1, 4, 0, 0, 0, 8];
+    let mut de = Deserializer::new(BytesMut::from(v));
+    assert!(Record::deserialize(&mut de).is_err());
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[ensicoin(tlv)]
+pub struct CachedRecord {
+    #[ensicoin(tag = 1)]
+    id: u32,
+    #[ensicoin(skip)]
+    seen: u32,
+}
+
+#[test]
+fn tlv_skipped_field() {
+    let record = CachedRecord { id: 258, seen: 3 };
+    let v = record.serialize();
+    assert_eq!(vec![1, 1, 4, 0, 0, 1, 2], v.to_vec());
+    let mut de = Deserializer::new(BytesMut::from(v));
+    assert_eq!(
+        CachedRecord { id: 258, seen: 0 },
+        CachedRecord::deserialize(&mut de).unwrap()
+    );
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[ensicoin(tlv)]
+pub struct Inv {
+    #[ensicoin(tag = 1)]
+    count: u32,
+    #[ensicoin(tag = 2)]
+    tag: u8,
+    #[ensicoin(tag = 3)]
+    length: u8,
+    #[ensicoin(tag = 4)]
+    de: u8,
+}
+
+#[test]
+fn tlv_fields_named_like_locals() {
+    let inv = Inv {
+        count: 1,
+        tag: 2,
+        length: 3,
+        de: 4,
+    };
+    let mut de = Deserializer::new(BytesMut::from(inv.serialize()));
+    assert_eq!(inv, Inv::deserialize(&mut de).unwrap());
+}
@@ -0,0 +1,7 @@
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+#[derive(Deserialize)]
+pub struct Pair(#[ensicoin(skip)] u8, u32);
+
+fn main() {}
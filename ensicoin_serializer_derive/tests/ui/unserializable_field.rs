@@ -0,0 +1,17 @@
+extern crate bytes;
+extern crate ensicoin_serializer;
+
+use ensicoin_serializer::Serialize;
+
+#[macro_use]
+extern crate ensicoin_serializer_derive;
+
+pub struct NotSerializable;
+
+#[derive(Serialize)]
+pub struct Record {
+    id: u32,
+    payload: NotSerializable,
+}
+
+fn main() {}